@@ -1,8 +1,15 @@
-use constants::{AURA_INPUT_REPORT_SIZE, AURA_OUTPUT_REPORT_SIZE};
+use constants::{
+    AURA_CONFIG_TABLE_MAX_CHANNELS, AURA_HID_REPORT_ID, AURA_INPUT_REPORT_SIZE,
+    AURA_OUTPUT_REPORT_SIZE,
+};
 use int_enum::IntEnum;
 #[cfg(feature = "rgb-crate")]
 pub use rgb::RGB8;
 
+mod codec;
+
+pub use codec::{AuraInputResponse, AuraOutputRequest};
+
 pub mod constants {
     /// The HID Report ID that uses Asus for the HID output reports.
     pub const AURA_HID_REPORT_ID: u8 = 0xec;
@@ -10,11 +17,19 @@ pub mod constants {
     /// The maximum LED count that can be sent for change in a single direct LED update report.
     pub const AURA_MAX_DIRECT_LED_COUNT: u8 = 20;
 
+    /// The number of LEDs addressable by the mask of an effect colors
+    /// report.
+    pub const AURA_MAX_EFFECT_COLOR_COUNT: usize = 16;
+
     /// The length of an Aura firmware length string.
     pub const AURA_FIRMWARE_VERSION_LEN: u8 = 15;
 
     pub const AURA_OUTPUT_REPORT_SIZE: usize = 65;
 
+    /// The maximum number of channels that fit in a config table
+    /// response, given its 10 bytes header and 6 bytes per channel.
+    pub const AURA_CONFIG_TABLE_MAX_CHANNELS: usize = 9;
+
     // The original ASUS ROG Terminal firmware specifies IN transfer size
     // of 65 bytes. However, usbd-hid and synopsys-usb-otg (for STM32 with
     // OTG support) have limited the transfer size to wMaxPacketSize,
@@ -42,8 +57,19 @@ pub fn rgb_from_raw_slice(slice: &[u8]) -> &[RGB8] {
     }
 }
 
+// Derived by hand, since the fields of a packed struct can't be
+// borrowed.
+#[cfg(all(feature = "defmt", not(feature = "rgb-crate")))]
+impl defmt::Format for RGB8 {
+    fn format(&self, f: defmt::Formatter) {
+        let RGB8 { r, g, b } = *self;
+        defmt::write!(f, "RGB8 {{ r: {=u8}, g: {=u8}, b: {=u8} }}", r, g, b)
+    }
+}
+
 #[repr(u8)]
-#[derive(Clone, Copy, IntEnum)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, IntEnum)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum AuraEffect {
     Off = 0,
     Static = 1,
@@ -63,9 +89,124 @@ pub enum AuraEffect {
     Direct = 0xff,
 }
 
+/// The direction in which an animated effect moves along the strip.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default, IntEnum)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum AuraEffectDirection {
+    #[default]
+    Forward = 0,
+    Reverse = 1,
+}
+
+impl AuraEffectDirection {
+    /// Decodes the direction byte of a
+    /// [`AuraOutputReportType::SetEffect`] report. Anything other than
+    /// zero is understood as reverse.
+    pub fn from_raw(value: u8) -> Self {
+        if value == 0 {
+            Self::Forward
+        } else {
+            Self::Reverse
+        }
+    }
+}
+
+/// The parameters that come along with the effect in a
+/// [`AuraOutputReportType::SetEffect`] report.
+///
+/// The layout of the report, as far as it is known, is:
+///
+/// | Byte     | Content                                 |
+/// |----------|-----------------------------------------|
+/// | 2        | Channel                                 |
+/// | 3        | Shutdown flag                           |
+/// | 4        | Effect code (see [`AuraEffect`])        |
+/// | 5..8     | Primary color (RGB)                     |
+/// | 8        | Speed                                   |
+/// | 9        | Direction (see [`AuraEffectDirection`]) |
+/// | 10       | Brightness                              |
+/// | 11..14   | Secondary color (RGB)                   |
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct AuraEffectParams {
+    /// The main color of the effect. Used by every single-color
+    /// effect, like [`AuraEffect::Static`] or [`AuraEffect::Breathing`].
+    pub color: RGB8,
+
+    /// The secondary color of the effect, for the effects that blend
+    /// between two colors.
+    pub secondary_color: RGB8,
+
+    /// The speed of the effect. Lower values are slower.
+    pub speed: u8,
+
+    /// The direction of the effect, for the effects that move along
+    /// the strip.
+    pub direction: AuraEffectDirection,
+
+    /// The brightness of the effect, where 0xff is the full brightness.
+    pub brightness: u8,
+
+    /// Whether the effect is the one to be shown when the host is
+    /// shut down, instead of the one to be applied right away.
+    pub shutdown: bool,
+}
+
+impl AuraEffectParams {
+    /// Decodes the effect parameters from a
+    /// [`AuraOutputReportType::SetEffect`] report.
+    pub fn from_report(report: &AuraOutputReport) -> Result<Self, InvalidReportError> {
+        if report[0] != AURA_HID_REPORT_ID {
+            return Err(InvalidReportError::InvalidReportId);
+        }
+        if report[1] != AuraOutputReportType::SetEffect as u8 {
+            return Err(InvalidReportError::InvalidReportType);
+        }
+
+        Ok(Self {
+            color: rgb_from_raw_slice(&report[5..8])[0],
+            secondary_color: rgb_from_raw_slice(&report[11..14])[0],
+            speed: report[8],
+            direction: AuraEffectDirection::from_raw(report[9]),
+            brightness: report[10],
+            shutdown: report[3] != 0,
+        })
+    }
+
+    /// Encodes the effect parameters into a
+    /// [`AuraOutputReportType::SetEffect`] report.
+    pub fn write_to_report(&self, report: &mut AuraOutputReport) {
+        report[3] = self.shutdown as u8;
+        report[5..8].copy_from_slice(&[self.color.r, self.color.g, self.color.b]);
+        report[8] = self.speed;
+        report[9] = self.direction as u8;
+        report[10] = self.brightness;
+        report[11..14].copy_from_slice(&[
+            self.secondary_color.r,
+            self.secondary_color.g,
+            self.secondary_color.b,
+        ]);
+    }
+}
+
+impl Default for AuraEffectParams {
+    fn default() -> Self {
+        Self {
+            color: RGB8 { r: 0, g: 0, b: 0 },
+            secondary_color: RGB8 { r: 0, g: 0, b: 0 },
+            speed: 0,
+            direction: AuraEffectDirection::Forward,
+            brightness: 0xff,
+            shutdown: false,
+        }
+    }
+}
+
 /// The possible report types that the host can send to the device.
 #[repr(u8)]
 #[derive(Clone, Copy, IntEnum)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum AuraOutputReportType {
     /// The host is requesting the firmware version of the device.
     FirmwareVersionRequest = 0x82,
@@ -79,11 +220,29 @@ pub enum AuraOutputReportType {
     /// The host is requesting the device to set the LEDs of the
     /// device to a specific colors.
     SetDirectLeds = 0x40,
+
+    /// The host is requesting the device to set a preset effect in a
+    /// channel. This is the generic Aura USB counterpart of
+    /// [`AuraOutputReportType::SetEffect`], sent by OpenRGB's Aura USB
+    /// mainboard and addressable controllers.
+    SetMode = 0x35,
+
+    /// The host is setting the colors of the current effect for the
+    /// LEDs selected by a 16-bit mask. Sent by OpenRGB's Aura USB
+    /// mainboard and addressable controllers after
+    /// [`AuraOutputReportType::SetMode`].
+    SetEffectColors = 0x36,
+
+    /// The host is asking the device to commit the current effects
+    /// and colors, so they persist across power cycles. Sent by
+    /// OpenRGB's Aura USB controllers when saving a mode.
+    Commit = 0x3F,
 }
 
 /// The possible report types that the device can send to the host.
 #[repr(u8)]
 #[derive(Clone, Copy, IntEnum)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum AuraInputReportType {
     /// The firmware request was successfully completed.
     FirmwareVersionRequestOk = 0x02,
@@ -95,7 +254,350 @@ pub enum AuraInputReportType {
 pub type AuraOutputReport = [u8; AURA_OUTPUT_REPORT_SIZE];
 pub type AuraInputReport = [u8; AURA_INPUT_REPORT_SIZE];
 
+/// The reasons why a report can't be decoded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum InvalidReportError {
+    /// The report doesn't start with [`AURA_HID_REPORT_ID`].
     InvalidReportId,
-    InvalidReportType
+
+    /// The report type is not known.
+    InvalidReportType,
+
+    /// The report is shorter than what its content requires.
+    Truncated,
+
+    /// The report carries more LEDs than fit in it.
+    LedCountOutOfRange,
+
+    /// The config table carries more channels than fit in it.
+    ChannelCountOutOfRange,
+
+    /// The effect code is not a known [`AuraEffect`].
+    UnknownEffect,
+}
+
+/// The per-channel entry of an [`AuraConfigTable`].
+///
+/// Except for the LED count, the meaning of these bytes is unknown.
+/// They are exposed so the values sent by the original device can be
+/// reproduced, or tweaked if some host turns out to care about them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AuraChannelConfig {
+    /// Unknown. Always 0x00 on the original device.
+    pub unknown0: u8,
+
+    /// The number of LEDs attached to the channel.
+    pub led_count: u8,
+
+    /// Unknown. Always 0x01 on the original device.
+    pub unknown2: u8,
+
+    /// Unknown. Always 0x64 on the original device.
+    pub unknown3: u8,
+
+    /// Unknown. Always 0x01 on the original device.
+    pub unknown4: u8,
+
+    /// Unknown flags. 0x01 on every channel of the original device,
+    /// except for the last one, which carries 0x03. See
+    /// [`AuraConfigTable::with_logo_channel`].
+    pub flags: u8,
+}
+
+impl AuraChannelConfig {
+    pub const fn new(led_count: u8) -> Self {
+        Self {
+            unknown0: 0x00,
+            led_count,
+            unknown2: 0x01,
+            unknown3: 0x64,
+            unknown4: 0x01,
+            flags: 0x01,
+        }
+    }
+}
+
+impl Default for AuraChannelConfig {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+/// A channel addressed by the host.
+///
+/// The logo channel is addressed right after the last LED strip
+/// channel, so its index depends on the config table. It has a single
+/// LED.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum AuraChannel {
+    /// A LED strip channel, with its index.
+    Strip(u8),
+
+    /// The logo channel, with the index used by the host to address it.
+    Logo(u8),
+}
+
+impl AuraChannel {
+    /// The number of LEDs of the logo channel.
+    pub const LOGO_LED_COUNT: u8 = 1;
+
+    /// Returns the index used by the host to address the channel.
+    pub const fn index(&self) -> u8 {
+        match self {
+            AuraChannel::Strip(index) | AuraChannel::Logo(index) => *index,
+        }
+    }
+
+    pub const fn is_logo(&self) -> bool {
+        matches!(self, AuraChannel::Logo(_))
+    }
+}
+
+/// The config table the device reports to the host when it receives a
+/// [`AuraOutputReportType::ConfigTableRequest`].
+///
+/// From my own tests, Armoury Crate doesn't give a fluff about most of
+/// this data, except for the header (first 2 bytes) and, for whatever
+/// reason, the byte at index 8. OpenRGB, however, honors the LED count
+/// of each channel. The default value honors the original device
+/// behavior.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AuraConfigTable {
+    /// Unknown bytes right after the report header. `00 00 1f ff`
+    /// on the original device.
+    pub unknown_header: [u8; 4],
+
+    /// Unknown bytes right after the channel count. `1f 01 01` on the
+    /// original device. The middle one is the byte at index 8 of the
+    /// report, which Armoury Crate checks, so better leave it alone.
+    pub unknown_flags: [u8; 3],
+
+    /// Whether the device has a logo channel in addition to the LED
+    /// strip channels. Only reported to the host if the table has at
+    /// least one channel.
+    pub logo_channel: bool,
+
+    channel_count: u8,
+    channels: [AuraChannelConfig; AURA_CONFIG_TABLE_MAX_CHANNELS],
+}
+
+impl AuraConfigTable {
+    /// Flag set in the last channel entry when the device has a logo
+    /// channel.
+    ///
+    /// Experimental: the original device sets it in its fourth channel,
+    /// and it's the only difference with the others, but no host has
+    /// been seen relying on it. Hosts may just as well ignore it, and
+    /// address the logo right after the last channel regardless.
+    pub const LOGO_CHANNEL_FLAG: u8 = 0x02;
+
+    /// The LED count of every channel on the original device.
+    pub const ROG_TERMINAL_CHANNEL_LED_COUNT: u8 = 0x5a;
+
+    /// The config table sent by the original ROG Aura Terminal: four
+    /// channels of 90 LEDs each, plus the logo channel.
+    pub const ROG_TERMINAL: Self = Self::new()
+        .with_channel(Self::ROG_TERMINAL_CHANNEL_LED_COUNT)
+        .with_channel(Self::ROG_TERMINAL_CHANNEL_LED_COUNT)
+        .with_channel(Self::ROG_TERMINAL_CHANNEL_LED_COUNT)
+        .with_channel(Self::ROG_TERMINAL_CHANNEL_LED_COUNT)
+        .with_logo_channel(true);
+
+    /// Creates a config table with no channels.
+    pub const fn new() -> Self {
+        Self {
+            unknown_header: [0x00, 0x00, 0x1f, 0xff],
+            unknown_flags: [0x1f, 0x01, 0x01],
+            logo_channel: false,
+            channel_count: 0,
+            channels: [AuraChannelConfig::new(0); AURA_CONFIG_TABLE_MAX_CHANNELS],
+        }
+    }
+
+    /// Appends a channel with the given number of LEDs.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the table already has
+    /// [`AURA_CONFIG_TABLE_MAX_CHANNELS`] channels. When building a
+    /// `const` table, that's a compile-time error instead. See
+    /// [`AuraConfigTable::try_with_channel`] for tables built at
+    /// runtime.
+    pub const fn with_channel(self, led_count: u8) -> Self {
+        self.with_channel_config(AuraChannelConfig::new(led_count))
+    }
+
+    /// Appends a channel with the given configuration.
+    ///
+    /// # Panics
+    ///
+    /// Like [`AuraConfigTable::with_channel`].
+    pub const fn with_channel_config(self, channel: AuraChannelConfig) -> Self {
+        match self.try_with_channel_config(channel) {
+            Some(table) => table,
+            None => panic!("Too many channels for an Aura config table"),
+        }
+    }
+
+    /// Appends a channel with the given number of LEDs, or returns
+    /// `None` if the table already has
+    /// [`AURA_CONFIG_TABLE_MAX_CHANNELS`] channels.
+    pub const fn try_with_channel(self, led_count: u8) -> Option<Self> {
+        self.try_with_channel_config(AuraChannelConfig::new(led_count))
+    }
+
+    /// Appends a channel with the given configuration, or returns
+    /// `None` if the table already has
+    /// [`AURA_CONFIG_TABLE_MAX_CHANNELS`] channels.
+    pub const fn try_with_channel_config(mut self, channel: AuraChannelConfig) -> Option<Self> {
+        if self.channel_count as usize >= AURA_CONFIG_TABLE_MAX_CHANNELS {
+            return None;
+        }
+
+        self.channels[self.channel_count as usize] = channel;
+        self.channel_count += 1;
+        Some(self)
+    }
+
+    /// Sets whether the device advertises a logo channel. See
+    /// [`AuraConfigTable::LOGO_CHANNEL_FLAG`].
+    ///
+    /// # Panics
+    ///
+    /// The flag is carried by the last channel entry, so this panics if
+    /// a logo channel is set in a table with no channels yet. When
+    /// building a `const` table, that's a compile-time error instead.
+    pub const fn with_logo_channel(mut self, logo_channel: bool) -> Self {
+        assert!(
+            !logo_channel || self.channel_count > 0,
+            "The logo channel needs at least one channel in the Aura config table"
+        );
+        self.logo_channel = logo_channel;
+        self
+    }
+
+    /// Sets the unknown bytes that follow the report header.
+    pub const fn with_unknown_header(mut self, unknown_header: [u8; 4]) -> Self {
+        self.unknown_header = unknown_header;
+        self
+    }
+
+    /// Sets the unknown bytes that follow the channel count.
+    pub const fn with_unknown_flags(mut self, unknown_flags: [u8; 3]) -> Self {
+        self.unknown_flags = unknown_flags;
+        self
+    }
+
+    /// Returns the number of LED strip channels in the table.
+    pub const fn channel_count(&self) -> u8 {
+        self.channel_count
+    }
+
+    /// Returns the index the host uses to address the logo channel, if
+    /// the table advertises one.
+    pub const fn logo_channel_index(&self) -> Option<u8> {
+        if self.logo_channel {
+            Some(self.channel_count)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the channel addressed by the host with `index`. Indices
+    /// beyond the last channel are returned as strip channels.
+    pub const fn resolve_channel(&self, index: u8) -> AuraChannel {
+        if self.logo_channel && index == self.channel_count {
+            AuraChannel::Logo(index)
+        } else {
+            AuraChannel::Strip(index)
+        }
+    }
+
+    /// Returns the configuration of the LED strip channels.
+    pub fn channels(&self) -> &[AuraChannelConfig] {
+        &self.channels[..self.channel_count as usize]
+    }
+
+    /// Returns a mutable reference to the configuration of the LED
+    /// strip channels.
+    pub fn channels_mut(&mut self) -> &mut [AuraChannelConfig] {
+        &mut self.channels[..self.channel_count as usize]
+    }
+
+    /// Serializes the config table into the report sent to the host.
+    pub fn to_report(&self) -> AuraInputReport {
+        let mut report: AuraInputReport = [0; AURA_INPUT_REPORT_SIZE];
+        report[0] = AURA_HID_REPORT_ID;
+        report[1] = AuraInputReportType::ConfigTableRequestOk as u8;
+        report[2..6].copy_from_slice(&self.unknown_header);
+        report[6] = self.channel_count;
+        report[7..10].copy_from_slice(&self.unknown_flags);
+
+        for (i, channel) in self.channels().iter().enumerate() {
+            let mut flags = channel.flags;
+            if self.logo_channel && i + 1 == self.channel_count as usize {
+                flags |= Self::LOGO_CHANNEL_FLAG;
+            }
+
+            let base = 10 + i * 6;
+            report[base..base + 6].copy_from_slice(&[
+                channel.unknown0,
+                channel.led_count,
+                channel.unknown2,
+                channel.unknown3,
+                channel.unknown4,
+                flags,
+            ]);
+        }
+
+        report
+    }
+
+    /// Deserializes a config table from the report sent by the device.
+    pub fn from_report(report: &AuraInputReport) -> Result<Self, InvalidReportError> {
+        if report[0] != AURA_HID_REPORT_ID {
+            return Err(InvalidReportError::InvalidReportId);
+        }
+        if report[1] != AuraInputReportType::ConfigTableRequestOk as u8 {
+            return Err(InvalidReportError::InvalidReportType);
+        }
+
+        let channel_count = report[6];
+        if channel_count as usize > AURA_CONFIG_TABLE_MAX_CHANNELS {
+            return Err(InvalidReportError::ChannelCountOutOfRange);
+        }
+
+        let mut table = Self::new();
+        table.unknown_header.copy_from_slice(&report[2..6]);
+        table.unknown_flags.copy_from_slice(&report[7..10]);
+
+        for i in 0..channel_count as usize {
+            let base = 10 + i * 6;
+            let mut channel = AuraChannelConfig {
+                unknown0: report[base],
+                led_count: report[base + 1],
+                unknown2: report[base + 2],
+                unknown3: report[base + 3],
+                unknown4: report[base + 4],
+                flags: report[base + 5],
+            };
+
+            if i + 1 == channel_count as usize && channel.flags & Self::LOGO_CHANNEL_FLAG != 0 {
+                channel.flags &= !Self::LOGO_CHANNEL_FLAG;
+                table.logo_channel = true;
+            }
+
+            table = table.with_channel_config(channel);
+        }
+
+        Ok(table)
+    }
+}
+
+impl Default for AuraConfigTable {
+    fn default() -> Self {
+        Self::ROG_TERMINAL
+    }
 }
@@ -0,0 +1,294 @@
+//! Encoding and decoding of the Aura HID reports.
+//!
+//! Both sides of the conversation share these definitions: the device
+//! decodes the [`AuraOutputRequest`]s sent by the host and encodes the
+//! [`AuraInputResponse`]s, while a host does the opposite.
+
+use tinyvec::ArrayVec;
+
+use super::constants::{
+    AURA_FIRMWARE_VERSION_LEN, AURA_HID_REPORT_ID, AURA_INPUT_REPORT_SIZE,
+    AURA_MAX_DIRECT_LED_COUNT, AURA_MAX_EFFECT_COLOR_COUNT, AURA_OUTPUT_REPORT_SIZE,
+};
+use super::{
+    rgb_from_raw_slice, AuraConfigTable, AuraEffect, AuraEffectParams, AuraInputReport,
+    AuraInputReportType, AuraOutputReport, AuraOutputReportType, InvalidReportError, RGB8,
+};
+
+/// A request sent by the host to the device.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AuraOutputRequest {
+    /// See [`AuraOutputReportType::FirmwareVersionRequest`].
+    FirmwareVersion,
+
+    /// See [`AuraOutputReportType::ConfigTableRequest`].
+    ConfigTable,
+
+    /// See [`AuraOutputReportType::SetEffect`].
+    SetEffect {
+        channel: u8,
+        effect: AuraEffect,
+        params: AuraEffectParams,
+    },
+
+    /// See [`AuraOutputReportType::SetDirectLeds`].
+    SetDirectLeds {
+        channel: u8,
+        offset: u8,
+        apply: bool,
+        led_data: ArrayVec<[RGB8; AURA_MAX_DIRECT_LED_COUNT as usize]>,
+    },
+
+    /// See [`AuraOutputReportType::SetMode`].
+    SetMode {
+        channel: u8,
+        effect: AuraEffect,
+        shutdown: bool,
+    },
+
+    /// See [`AuraOutputReportType::SetEffectColors`]. Only the entries
+    /// of `colors` whose bit is set in `led_mask` are meaningful.
+    SetEffectColors {
+        led_mask: u16,
+        shutdown: bool,
+        colors: [RGB8; AURA_MAX_EFFECT_COLOR_COUNT],
+    },
+
+    /// See [`AuraOutputReportType::Commit`].
+    Commit,
+}
+
+/// The value OpenRGB sends along with a commit request.
+const AURA_COMMIT_MAGIC: u8 = 0x55;
+
+fn decode_effect(code: u8) -> Result<AuraEffect, InvalidReportError> {
+    AuraEffect::try_from(code).map_err(|_| InvalidReportError::UnknownEffect)
+}
+
+fn write_rgb(buf: &mut [u8], color: &RGB8) {
+    buf[0] = color.r;
+    buf[1] = color.g;
+    buf[2] = color.b;
+}
+
+impl AuraOutputRequest {
+    /// Decodes a report sent by the host.
+    pub fn decode(report: &AuraOutputReport) -> Result<Self, InvalidReportError> {
+        if report[0] != AURA_HID_REPORT_ID {
+            return Err(InvalidReportError::InvalidReportId);
+        }
+
+        let report_type = AuraOutputReportType::try_from(report[1])
+            .map_err(|_| InvalidReportError::InvalidReportType)?;
+
+        Ok(match report_type {
+            AuraOutputReportType::FirmwareVersionRequest => Self::FirmwareVersion,
+            AuraOutputReportType::ConfigTableRequest => Self::ConfigTable,
+            AuraOutputReportType::SetEffect => Self::SetEffect {
+                channel: report[2],
+                effect: decode_effect(report[4])?,
+                params: AuraEffectParams::from_report(report)?,
+            },
+            AuraOutputReportType::SetDirectLeds => {
+                let num_leds = report[4];
+                if num_leds > AURA_MAX_DIRECT_LED_COUNT {
+                    return Err(InvalidReportError::LedCountOutOfRange);
+                }
+
+                let mut led_data = ArrayVec::new();
+                led_data
+                    .extend_from_slice(rgb_from_raw_slice(&report[5..5 + num_leds as usize * 3]));
+
+                Self::SetDirectLeds {
+                    channel: report[2] & 0x7f,
+                    offset: report[3],
+                    apply: (report[2] & 0x80) > 0,
+                    led_data,
+                }
+            }
+            AuraOutputReportType::SetMode => Self::SetMode {
+                channel: report[2],
+                effect: decode_effect(report[5])?,
+                shutdown: report[4] != 0,
+            },
+            AuraOutputReportType::SetEffectColors => {
+                let mut colors = [RGB8 { r: 0, g: 0, b: 0 }; AURA_MAX_EFFECT_COLOR_COUNT];
+                colors.copy_from_slice(rgb_from_raw_slice(
+                    &report[5..5 + AURA_MAX_EFFECT_COLOR_COUNT * 3],
+                ));
+
+                Self::SetEffectColors {
+                    led_mask: u16::from_be_bytes([report[2], report[3]]),
+                    shutdown: report[4] != 0,
+                    colors,
+                }
+            }
+            AuraOutputReportType::Commit => Self::Commit,
+        })
+    }
+
+    /// Decodes a report sent by the host from a slice, that may be
+    /// shorter than a full report if the transport dropped its trailing
+    /// zeros. Bytes beyond the size of a full report are ignored.
+    pub fn decode_slice(data: &[u8]) -> Result<Self, InvalidReportError> {
+        if data.len() < 2 {
+            return Err(InvalidReportError::Truncated);
+        }
+
+        // The LED data of a direct LED update can't be zero-filled,
+        // since it would be silently turning off LEDs.
+        if data[1] == AuraOutputReportType::SetDirectLeds as u8 {
+            let num_leds = data.get(4).copied().unwrap_or(0);
+            let required = 5 + num_leds.min(AURA_MAX_DIRECT_LED_COUNT) as usize * 3;
+            if data.len() < required {
+                return Err(InvalidReportError::Truncated);
+            }
+        }
+
+        let len = data.len().min(AURA_OUTPUT_REPORT_SIZE);
+        let mut report: AuraOutputReport = [0; AURA_OUTPUT_REPORT_SIZE];
+        report[..len].copy_from_slice(&data[..len]);
+        Self::decode(&report)
+    }
+
+    /// Returns the report type used to send this request.
+    pub fn report_type(&self) -> AuraOutputReportType {
+        match self {
+            Self::FirmwareVersion => AuraOutputReportType::FirmwareVersionRequest,
+            Self::ConfigTable => AuraOutputReportType::ConfigTableRequest,
+            Self::SetEffect { .. } => AuraOutputReportType::SetEffect,
+            Self::SetDirectLeds { .. } => AuraOutputReportType::SetDirectLeds,
+            Self::SetMode { .. } => AuraOutputReportType::SetMode,
+            Self::SetEffectColors { .. } => AuraOutputReportType::SetEffectColors,
+            Self::Commit => AuraOutputReportType::Commit,
+        }
+    }
+
+    /// Encodes the request into a report ready to be sent to the
+    /// device.
+    pub fn encode(&self) -> AuraOutputReport {
+        let mut report: AuraOutputReport = [0; AURA_OUTPUT_REPORT_SIZE];
+        report[0] = AURA_HID_REPORT_ID;
+        report[1] = self.report_type() as u8;
+
+        match self {
+            Self::FirmwareVersion | Self::ConfigTable => {}
+            Self::SetEffect {
+                channel,
+                effect,
+                params,
+            } => {
+                report[2] = *channel;
+                report[4] = *effect as u8;
+                params.write_to_report(&mut report);
+            }
+            Self::SetDirectLeds {
+                channel,
+                offset,
+                apply,
+                led_data,
+            } => {
+                report[2] = (channel & 0x7f) | if *apply { 0x80 } else { 0x00 };
+                report[3] = *offset;
+                report[4] = led_data.len() as u8;
+                for (i, color) in led_data.iter().enumerate() {
+                    write_rgb(&mut report[5 + i * 3..], color);
+                }
+            }
+            Self::SetMode {
+                channel,
+                effect,
+                shutdown,
+            } => {
+                report[2] = *channel;
+                report[4] = *shutdown as u8;
+                report[5] = *effect as u8;
+            }
+            Self::SetEffectColors {
+                led_mask,
+                shutdown,
+                colors,
+            } => {
+                report[2..4].copy_from_slice(&led_mask.to_be_bytes());
+                report[4] = *shutdown as u8;
+                for (i, color) in colors.iter().enumerate() {
+                    write_rgb(&mut report[5 + i * 3..], color);
+                }
+            }
+            Self::Commit => {
+                report[2] = AURA_COMMIT_MAGIC;
+            }
+        }
+
+        report
+    }
+}
+
+/// A response sent by the device to the host.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AuraInputResponse {
+    /// The response to [`AuraOutputRequest::FirmwareVersion`].
+    FirmwareVersion([u8; AURA_FIRMWARE_VERSION_LEN as usize]),
+
+    /// The response to [`AuraOutputRequest::ConfigTable`].
+    ConfigTable(AuraConfigTable),
+}
+
+impl AuraInputResponse {
+    /// Decodes a report sent by the device.
+    pub fn decode(report: &AuraInputReport) -> Result<Self, InvalidReportError> {
+        if report[0] != AURA_HID_REPORT_ID {
+            return Err(InvalidReportError::InvalidReportId);
+        }
+
+        let report_type = AuraInputReportType::try_from(report[1])
+            .map_err(|_| InvalidReportError::InvalidReportType)?;
+
+        match report_type {
+            AuraInputReportType::FirmwareVersionRequestOk => {
+                let mut version = [0; AURA_FIRMWARE_VERSION_LEN as usize];
+                version.copy_from_slice(&report[2..2 + AURA_FIRMWARE_VERSION_LEN as usize]);
+                Ok(Self::FirmwareVersion(version))
+            }
+            AuraInputReportType::ConfigTableRequestOk => {
+                AuraConfigTable::from_report(report).map(Self::ConfigTable)
+            }
+        }
+    }
+
+    /// Decodes a report sent by the device from a slice, that may be
+    /// shorter than a full report. Bytes beyond the size of a full
+    /// report are ignored.
+    pub fn decode_slice(data: &[u8]) -> Result<Self, InvalidReportError> {
+        if data.len() < 2 {
+            return Err(InvalidReportError::Truncated);
+        }
+
+        let len = data.len().min(AURA_INPUT_REPORT_SIZE);
+        let mut report: AuraInputReport = [0; AURA_INPUT_REPORT_SIZE];
+        report[..len].copy_from_slice(&data[..len]);
+        Self::decode(&report)
+    }
+
+    /// Returns the report type used to send this response.
+    pub fn report_type(&self) -> AuraInputReportType {
+        match self {
+            Self::FirmwareVersion(_) => AuraInputReportType::FirmwareVersionRequestOk,
+            Self::ConfigTable(_) => AuraInputReportType::ConfigTableRequestOk,
+        }
+    }
+
+    /// Encodes the response into a report ready to be sent to the host.
+    pub fn encode(&self) -> AuraInputReport {
+        match self {
+            Self::FirmwareVersion(version) => {
+                let mut report: AuraInputReport = [0; AURA_INPUT_REPORT_SIZE];
+                report[0] = AURA_HID_REPORT_ID;
+                report[1] = self.report_type() as u8;
+                report[2..2 + version.len()].copy_from_slice(version);
+                report
+            }
+            Self::ConfigTable(config_table) => config_table.to_report(),
+        }
+    }
+}
@@ -0,0 +1,103 @@
+//! Prints the Aura reports of a usbmon capture, and optionally exports
+//! them as a session log.
+//!
+//! ```text
+//! aura-capture <capture> [--vid-pid 0b05:1889] [--device <bus>.<address>] [--export <log>]
+//! ```
+
+use std::process::ExitCode;
+
+use asus_rog_terminal_usb_device::capture::{
+    encode_session_log, import_capture, write_transcript, CaptureFilter,
+};
+
+const USAGE: &str =
+    "usage: aura-capture <capture> [--vid-pid 0b05:1889] [--device <bus>.<address>] [--export <log>]";
+
+struct Args {
+    capture: String,
+    filter: CaptureFilter,
+    export: Option<String>,
+}
+
+fn parse_pair<A, B>(value: &str, separator: char, radix: u32) -> Option<(A, B)>
+where
+    A: TryFrom<u32>,
+    B: TryFrom<u32>,
+{
+    let (a, b) = value.split_once(separator)?;
+    let a = u32::from_str_radix(a, radix).ok()?.try_into().ok()?;
+    let b = u32::from_str_radix(b, radix).ok()?.try_into().ok()?;
+    Some((a, b))
+}
+
+fn parse_args() -> Option<Args> {
+    let mut args = std::env::args().skip(1);
+    let mut capture = None;
+    let mut filter = CaptureFilter::ROG_TERMINAL;
+    let mut export = None;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--vid-pid" => {
+                let (vendor_id, product_id) = parse_pair(&args.next()?, ':', 16)?;
+                filter = filter.with_vid_pid(vendor_id, product_id);
+            }
+            "--device" => {
+                let (bus, address) = parse_pair(&args.next()?, '.', 10)?;
+                filter = filter.with_device(bus, address);
+            }
+            "--export" => export = Some(args.next()?),
+            _ if capture.is_none() && !arg.starts_with("--") => capture = Some(arg),
+            _ => return None,
+        }
+    }
+
+    Some(Args {
+        capture: capture?,
+        filter,
+        export,
+    })
+}
+
+fn main() -> ExitCode {
+    let Some(args) = parse_args() else {
+        eprintln!("{USAGE}");
+        return ExitCode::FAILURE;
+    };
+
+    let capture = match std::fs::read(&args.capture) {
+        Ok(capture) => capture,
+        Err(e) => {
+            eprintln!("Can't read {}: {e}", args.capture);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let records = match import_capture(&capture, &args.filter) {
+        Ok(records) => records,
+        Err(e) => {
+            eprintln!("Can't import {}: {e:?}", args.capture);
+            return ExitCode::FAILURE;
+        }
+    };
+    if records.is_empty() {
+        eprintln!(
+            "No reports found. If the capture doesn't include the enumeration of the device, \
+             pass its bus and address with --device."
+        );
+    }
+
+    let mut transcript = String::new();
+    write_transcript(&records, &mut transcript).expect("Writing to a String can't fail");
+    print!("{transcript}");
+
+    if let Some(export) = args.export {
+        if let Err(e) = std::fs::write(&export, encode_session_log(&records)) {
+            eprintln!("Can't write {export}: {e}");
+            return ExitCode::FAILURE;
+        }
+    }
+
+    ExitCode::SUCCESS
+}
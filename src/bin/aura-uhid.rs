@@ -0,0 +1,101 @@
+//! Emulates a ROG Aura Terminal through `/dev/uhid`, drawing the LEDs
+//! set by the host in the terminal.
+//!
+//! ```text
+//! sudo aura-uhid
+//! ```
+
+use std::io::Write;
+use std::process::ExitCode;
+
+use asus_rog_terminal_usb_device::aura::{AuraConfigTable, RGB8};
+use asus_rog_terminal_usb_device::engine::AuraProtocolEngine;
+use asus_rog_terminal_usb_device::framebuffer::AuraFramebuffer;
+use asus_rog_terminal_usb_device::profile::DeviceProfile;
+use asus_rog_terminal_usb_device::uhid::{UhidDevice, UhidEvent};
+use asus_rog_terminal_usb_device::RogTerminalMessage;
+
+// The four strips of the Terminal, plus its logo.
+const CHANNELS: usize = 5;
+const LEDS: usize = AuraConfigTable::ROG_TERMINAL_CHANNEL_LED_COUNT as usize;
+
+fn draw(framebuffer: &AuraFramebuffer<CHANNELS, LEDS>, status: &str) {
+    let mut out = String::from("\x1b[H\x1b[2J");
+    for channel in 0..CHANNELS {
+        out.push_str(&format!("{channel} "));
+        let leds = framebuffer.front(channel as u8).unwrap();
+        let leds = if channel == CHANNELS - 1 {
+            &leds[..1]
+        } else {
+            &leds[..]
+        };
+        for led in leds {
+            let RGB8 { r, g, b } = *led;
+            out.push_str(&format!("\x1b[38;2;{r};{g};{b}m\u{2588}"));
+        }
+        out.push_str("\x1b[0m\n");
+    }
+    out.push_str(status);
+    out.push('\n');
+
+    let mut stdout = std::io::stdout().lock();
+    stdout.write_all(out.as_bytes()).ok();
+    stdout.flush().ok();
+}
+
+fn main() -> ExitCode {
+    let profile = DeviceProfile::ROG_TERMINAL;
+    let mut device = match UhidDevice::create(&profile, AuraProtocolEngine::from_profile(&profile))
+    {
+        Ok(device) => device,
+        Err(e) => {
+            eprintln!("Can't create the UHID device: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let mut framebuffer = AuraFramebuffer::<CHANNELS, LEDS>::new();
+    let mut status = String::from("Waiting for the host");
+    draw(&framebuffer, &status);
+
+    loop {
+        let event = match device.process_event() {
+            Ok(event) => event,
+            Err(e) => {
+                eprintln!("UHID error: {e}");
+                return ExitCode::FAILURE;
+            }
+        };
+
+        let mut redraw = event != UhidEvent::Report;
+        if redraw {
+            status = format!("{event:?}");
+        }
+
+        while let Some(message) = device.engine_mut().poll_next_message() {
+            match &message {
+                RogTerminalMessage::UpdateLeds { .. } => {
+                    redraw |= framebuffer.apply(&message).is_some();
+                }
+                RogTerminalMessage::SetEffect {
+                    channel, effect, ..
+                }
+                | RogTerminalMessage::SetMode {
+                    channel, effect, ..
+                } => {
+                    status = format!("Channel {channel:?} set to {effect:?}");
+                    redraw = true;
+                }
+                RogTerminalMessage::Commit => {
+                    status = String::from("Commit");
+                    redraw = true;
+                }
+                _ => {}
+            }
+        }
+
+        if redraw {
+            draw(&framebuffer, &status);
+        }
+    }
+}
@@ -0,0 +1,481 @@
+//! Importer of Linux usbmon captures, as written by Wireshark or
+//! tcpdump in the pcap and pcapng formats.
+//!
+//! The Aura reports exchanged with a device are picked out of the
+//! capture as [`SessionRecord`]s, so they can be printed with
+//! [`write_transcript`] or saved with [`encode_session_log`] and
+//! replayed as test fixtures.
+//!
+//! usbmon only tells devices apart by their bus and address, so the
+//! device is recognized by the VID/PID of its device descriptor, which
+//! requires the capture to include its enumeration. Otherwise, its bus
+//! and address have to be given in the [`CaptureFilter`].
+//!
+//! ```ignore
+//! let capture = std::fs::read("terminal.pcapng")?;
+//! let records = import_capture(&capture, &CaptureFilter::ROG_TERMINAL)?;
+//! let mut transcript = String::new();
+//! write_transcript(&records, &mut transcript)?;
+//! ```
+
+use core::fmt;
+use std::collections::HashMap;
+use std::vec::Vec;
+
+use crate::aura::constants::AURA_HID_REPORT_ID;
+use crate::aura::{AuraInputResponse, AuraOutputRequest};
+use crate::profile::DeviceProfile;
+use crate::session::{
+    encode_session_header, SessionDirection, SessionRecord, SESSION_RECORD_MAX_SIZE,
+};
+
+const LINKTYPE_USB_LINUX: u32 = 189;
+const LINKTYPE_USB_LINUX_MMAPPED: u32 = 220;
+
+const PCAP_MAGIC_US: u32 = 0xa1b2_c3d4;
+const PCAP_MAGIC_NS: u32 = 0xa1b2_3c4d;
+const PCAPNG_SECTION_HEADER: u32 = 0x0a0d_0d0a;
+const PCAPNG_BYTE_ORDER_MAGIC: u32 = 0x1a2b_3c4d;
+const PCAPNG_INTERFACE_DESCRIPTION: u32 = 1;
+const PCAPNG_SIMPLE_PACKET: u32 = 3;
+const PCAPNG_ENHANCED_PACKET: u32 = 6;
+
+const URB_SUBMIT: u8 = b'S';
+const URB_COMPLETE: u8 = b'C';
+const XFER_INTERRUPT: u8 = 1;
+const XFER_CONTROL: u8 = 2;
+
+const HID_REQ_SET_REPORT: u8 = 0x09;
+const HID_REPORT_TYPE_OUTPUT: u8 = 0x02;
+const REQ_GET_DESCRIPTOR: u8 = 0x06;
+const DESCRIPTOR_TYPE_DEVICE: u8 = 0x01;
+
+/// The devices whose reports are imported.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CaptureFilter {
+    pub vendor_id: u16,
+    pub product_id: u16,
+
+    /// The bus and address of the device, for captures that don't
+    /// include its enumeration. Only this device is imported if set.
+    pub device: Option<(u16, u8)>,
+}
+
+impl CaptureFilter {
+    pub const ROG_TERMINAL: CaptureFilter =
+        CaptureFilter::from_profile(&DeviceProfile::ROG_TERMINAL);
+
+    pub const fn from_profile(profile: &DeviceProfile) -> Self {
+        Self {
+            vendor_id: profile.vendor_id,
+            product_id: profile.product_id,
+            device: None,
+        }
+    }
+
+    pub const fn with_vid_pid(mut self, vendor_id: u16, product_id: u16) -> Self {
+        self.vendor_id = vendor_id;
+        self.product_id = product_id;
+        self
+    }
+
+    pub const fn with_device(mut self, bus: u16, address: u8) -> Self {
+        self.device = Some((bus, address));
+        self
+    }
+}
+
+impl Default for CaptureFilter {
+    fn default() -> Self {
+        Self::ROG_TERMINAL
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CaptureError {
+    /// The file is neither a pcap nor a pcapng capture.
+    UnknownFormat,
+
+    /// The capture ends in the middle of a header or a packet.
+    Truncated,
+
+    /// The capture wasn't taken with usbmon, with its link type.
+    UnsupportedLinkType(u32),
+}
+
+#[derive(Clone, Copy)]
+enum Endian {
+    Little,
+    Big,
+}
+
+impl Endian {
+    fn u16(self, data: &[u8], at: usize) -> Option<u16> {
+        let bytes = data.get(at..at + 2)?.try_into().ok()?;
+        Some(match self {
+            Endian::Little => u16::from_le_bytes(bytes),
+            Endian::Big => u16::from_be_bytes(bytes),
+        })
+    }
+
+    fn u32(self, data: &[u8], at: usize) -> Option<u32> {
+        let bytes = data.get(at..at + 4)?.try_into().ok()?;
+        Some(match self {
+            Endian::Little => u32::from_le_bytes(bytes),
+            Endian::Big => u32::from_be_bytes(bytes),
+        })
+    }
+
+    fn u64(self, data: &[u8], at: usize) -> Option<u64> {
+        let bytes = data.get(at..at + 8)?.try_into().ok()?;
+        Some(match self {
+            Endian::Little => u64::from_le_bytes(bytes),
+            Endian::Big => u64::from_be_bytes(bytes),
+        })
+    }
+}
+
+/// The header usbmon prepends to every packet. Its fields are in the
+/// byte order of the machine that took the capture, assumed to be the
+/// one of the capture file.
+struct UsbmonPacket<'c> {
+    id: u64,
+    event_type: u8,
+    xfer_type: u8,
+    endpoint: u8,
+    address: u8,
+    bus: u16,
+    setup: Option<[u8; 8]>,
+    timestamp_us: u64,
+    status: i32,
+    data: &'c [u8],
+}
+
+impl<'c> UsbmonPacket<'c> {
+    fn parse(packet: &'c [u8], link_type: u32, endian: Endian) -> Option<Self> {
+        let header_len = match link_type {
+            LINKTYPE_USB_LINUX => 48,
+            LINKTYPE_USB_LINUX_MMAPPED => 64,
+            _ => return None,
+        };
+        if packet.len() < header_len {
+            return None;
+        }
+
+        let seconds = endian.u64(packet, 16)?;
+        let micros = endian.u32(packet, 24)?;
+        let captured = endian.u32(packet, 36)? as usize;
+        let data_end = (header_len + captured).min(packet.len());
+
+        Some(Self {
+            id: endian.u64(packet, 0)?,
+            event_type: packet[8],
+            xfer_type: packet[9],
+            endpoint: packet[10],
+            address: packet[11],
+            bus: endian.u16(packet, 12)?,
+            setup: (packet[14] == 0).then(|| packet[40..48].try_into().unwrap()),
+            timestamp_us: seconds.wrapping_mul(1_000_000).wrapping_add(micros as u64),
+            status: endian.u32(packet, 28)? as i32,
+            data: &packet[header_len..data_end],
+        })
+    }
+
+    fn device(&self) -> (u16, u8) {
+        (self.bus, self.address)
+    }
+
+    fn is_in(&self) -> bool {
+        self.endpoint & 0x80 != 0
+    }
+}
+
+/// Follows the transfers of a capture, keeping the Aura reports of the
+/// filtered device.
+struct Importer<'f> {
+    filter: &'f CaptureFilter,
+    devices: HashMap<(u16, u8), (u16, u16)>,
+    // Submitted URBs whose completion is still to be seen: the setup
+    // packet of control transfers, and the data of output reports.
+    pending_setups: HashMap<u64, [u8; 8]>,
+    pending_reports: HashMap<u64, Vec<u8>>,
+    first_timestamp_us: Option<u64>,
+    records: Vec<SessionRecord>,
+}
+
+impl<'f> Importer<'f> {
+    fn new(filter: &'f CaptureFilter) -> Self {
+        Self {
+            filter,
+            devices: HashMap::new(),
+            pending_setups: HashMap::new(),
+            pending_reports: HashMap::new(),
+            first_timestamp_us: None,
+            records: Vec::new(),
+        }
+    }
+
+    fn is_filtered_device(&self, device: (u16, u8)) -> bool {
+        match self.filter.device {
+            Some(filtered) => filtered == device,
+            None => {
+                self.devices.get(&device) == Some(&(self.filter.vendor_id, self.filter.product_id))
+            }
+        }
+    }
+
+    fn push_record(&mut self, timestamp_us: u64, direction: SessionDirection, report: &[u8]) {
+        let first = *self.first_timestamp_us.get_or_insert(timestamp_us);
+        self.records.push(SessionRecord::new(
+            timestamp_us.saturating_sub(first),
+            direction,
+            report,
+        ));
+    }
+
+    fn handle_packet(&mut self, packet: &UsbmonPacket) {
+        match (packet.event_type, packet.xfer_type) {
+            (URB_SUBMIT, XFER_CONTROL) => {
+                let Some(setup) = packet.setup else {
+                    return;
+                };
+                self.pending_setups.insert(packet.id, setup);
+
+                let [request_type, request, report_id, report_type, ..] = setup;
+                if request_type == 0x21
+                    && request == HID_REQ_SET_REPORT
+                    && report_type == HID_REPORT_TYPE_OUTPUT
+                    && report_id == AURA_HID_REPORT_ID
+                    && packet.data.first() == Some(&AURA_HID_REPORT_ID)
+                {
+                    self.pending_reports.insert(packet.id, packet.data.to_vec());
+                }
+            }
+            (URB_SUBMIT, XFER_INTERRUPT)
+                if !packet.is_in() && packet.data.first() == Some(&AURA_HID_REPORT_ID) =>
+            {
+                self.pending_reports.insert(packet.id, packet.data.to_vec());
+            }
+            (URB_COMPLETE, XFER_CONTROL) => {
+                let setup = self.pending_setups.remove(&packet.id);
+                if let Some(report) = self.pending_reports.remove(&packet.id) {
+                    if packet.status == 0 && self.is_filtered_device(packet.device()) {
+                        self.push_record(packet.timestamp_us, SessionDirection::Output, &report);
+                    }
+                    return;
+                }
+
+                // Learn the VID/PID of devices from their descriptor.
+                if let Some([0x80, REQ_GET_DESCRIPTOR, _, DESCRIPTOR_TYPE_DEVICE, ..]) = setup {
+                    if packet.status == 0
+                        && packet.data.len() >= 12
+                        && packet.data[1] == DESCRIPTOR_TYPE_DEVICE
+                    {
+                        let vendor_id = u16::from_le_bytes([packet.data[8], packet.data[9]]);
+                        let product_id = u16::from_le_bytes([packet.data[10], packet.data[11]]);
+                        self.devices
+                            .insert(packet.device(), (vendor_id, product_id));
+                    }
+                }
+            }
+            (URB_COMPLETE, XFER_INTERRUPT) => {
+                if let Some(report) = self.pending_reports.remove(&packet.id) {
+                    if packet.status == 0 && self.is_filtered_device(packet.device()) {
+                        self.push_record(packet.timestamp_us, SessionDirection::Output, &report);
+                    }
+                } else if packet.is_in()
+                    && packet.status == 0
+                    && packet.data.first() == Some(&AURA_HID_REPORT_ID)
+                    && self.is_filtered_device(packet.device())
+                {
+                    self.push_record(packet.timestamp_us, SessionDirection::Input, packet.data);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn import_pcap(&mut self, capture: &[u8], endian: Endian) -> Result<(), CaptureError> {
+        let link_type = endian.u32(capture, 20).ok_or(CaptureError::Truncated)?;
+        if link_type != LINKTYPE_USB_LINUX && link_type != LINKTYPE_USB_LINUX_MMAPPED {
+            return Err(CaptureError::UnsupportedLinkType(link_type));
+        }
+
+        let mut at = 24;
+        while at < capture.len() {
+            let captured = endian.u32(capture, at + 8).ok_or(CaptureError::Truncated)? as usize;
+            let packet = capture
+                .get(at + 16..at + 16 + captured)
+                .ok_or(CaptureError::Truncated)?;
+            if let Some(packet) = UsbmonPacket::parse(packet, link_type, endian) {
+                self.handle_packet(&packet);
+            }
+            at += 16 + captured;
+        }
+
+        Ok(())
+    }
+
+    fn import_pcapng(&mut self, capture: &[u8]) -> Result<(), CaptureError> {
+        let mut endian = Endian::Little;
+        let mut link_types = Vec::new();
+        let mut at = 0;
+
+        while at < capture.len() {
+            let block_type = endian.u32(capture, at).ok_or(CaptureError::Truncated)?;
+            if block_type == PCAPNG_SECTION_HEADER {
+                // Each section has its own byte order and interfaces.
+                endian = match Endian::Little.u32(capture, at + 8) {
+                    Some(PCAPNG_BYTE_ORDER_MAGIC) => Endian::Little,
+                    Some(_) => Endian::Big,
+                    None => return Err(CaptureError::Truncated),
+                };
+                link_types.clear();
+            }
+
+            let block_len = endian.u32(capture, at + 4).ok_or(CaptureError::Truncated)? as usize;
+            if block_len < 12 {
+                return Err(CaptureError::Truncated);
+            }
+            let body = capture
+                .get(at + 8..at + block_len - 4)
+                .ok_or(CaptureError::Truncated)?;
+
+            match block_type {
+                PCAPNG_INTERFACE_DESCRIPTION => {
+                    let link_type = endian.u16(body, 0).ok_or(CaptureError::Truncated)?;
+                    link_types.push(link_type as u32);
+                }
+                PCAPNG_ENHANCED_PACKET => {
+                    let interface = endian.u32(body, 0).ok_or(CaptureError::Truncated)?;
+                    let captured = endian.u32(body, 12).ok_or(CaptureError::Truncated)? as usize;
+                    let packet = body.get(20..20 + captured).ok_or(CaptureError::Truncated)?;
+                    self.handle_pcapng_packet(&link_types, interface as usize, packet, endian);
+                }
+                PCAPNG_SIMPLE_PACKET => {
+                    let original = endian.u32(body, 0).ok_or(CaptureError::Truncated)? as usize;
+                    let packet = &body[4..];
+                    let packet = &packet[..original.min(packet.len())];
+                    self.handle_pcapng_packet(&link_types, 0, packet, endian);
+                }
+                _ => {}
+            }
+
+            at += block_len;
+        }
+
+        if !link_types.is_empty()
+            && !link_types
+                .iter()
+                .any(|t| *t == LINKTYPE_USB_LINUX || *t == LINKTYPE_USB_LINUX_MMAPPED)
+        {
+            return Err(CaptureError::UnsupportedLinkType(link_types[0]));
+        }
+
+        Ok(())
+    }
+
+    fn handle_pcapng_packet(
+        &mut self,
+        link_types: &[u32],
+        interface: usize,
+        packet: &[u8],
+        endian: Endian,
+    ) {
+        let Some(link_type) = link_types.get(interface) else {
+            return;
+        };
+        if let Some(packet) = UsbmonPacket::parse(packet, *link_type, endian) {
+            self.handle_packet(&packet);
+        }
+    }
+}
+
+/// Picks the Aura reports of a device out of a pcap or pcapng usbmon
+/// capture. The timestamps are relative to the first report.
+pub fn import_capture(
+    capture: &[u8],
+    filter: &CaptureFilter,
+) -> Result<Vec<SessionRecord>, CaptureError> {
+    let mut importer = Importer::new(filter);
+    let magic = Endian::Little
+        .u32(capture, 0)
+        .ok_or(CaptureError::UnknownFormat)?;
+
+    match magic {
+        PCAPNG_SECTION_HEADER => importer.import_pcapng(capture)?,
+        PCAP_MAGIC_US | PCAP_MAGIC_NS => importer.import_pcap(capture, Endian::Little)?,
+        _ if magic.swap_bytes() == PCAP_MAGIC_US || magic.swap_bytes() == PCAP_MAGIC_NS => {
+            importer.import_pcap(capture, Endian::Big)?
+        }
+        _ => return Err(CaptureError::UnknownFormat),
+    }
+
+    Ok(importer.records)
+}
+
+/// Writes a line per report, decoded with the definitions of this
+/// crate when possible, and in hex otherwise. Reports longer than the
+/// ones of their direction are flagged, as only their start was kept.
+pub fn write_transcript(records: &[SessionRecord], out: &mut impl fmt::Write) -> fmt::Result {
+    for record in records {
+        write!(
+            out,
+            "{:>6}.{:06} ",
+            record.timestamp_us / 1_000_000,
+            record.timestamp_us % 1_000_000
+        )?;
+
+        let decoded = match record.direction {
+            SessionDirection::Output => {
+                write!(out, "host -> device ")?;
+                AuraOutputRequest::decode_slice(record.report())
+                    .map(|request| write!(out, "{:?}", request))
+            }
+            SessionDirection::Input => {
+                write!(out, "device -> host ")?;
+                AuraInputResponse::decode_slice(record.report())
+                    .map(|response| write!(out, "{:?}", response))
+            }
+        };
+
+        match decoded {
+            Ok(written) => written?,
+            Err(e) => {
+                write!(out, "{:?}:", e)?;
+                let report = record.report();
+                let len = report.len() - report.iter().rev().take_while(|b| **b == 0).count();
+                for byte in &report[..len] {
+                    write!(out, " {:02x}", byte)?;
+                }
+            }
+        }
+        if record.truncated_bytes() > 0 {
+            let len = record.report().len() + record.truncated_bytes();
+            write!(out, " (truncated from {} bytes)", len)?;
+        }
+        writeln!(out)?;
+    }
+
+    Ok(())
+}
+
+/// Encodes records in the session log format of
+/// [`crate::session`], ready to be replayed.
+pub fn encode_session_log(records: &[SessionRecord]) -> Vec<u8> {
+    let mut log = Vec::new();
+    let mut buf = [0; SESSION_RECORD_MAX_SIZE];
+    let len = encode_session_header(&mut buf).expect("The header fits in a record");
+    log.extend_from_slice(&buf[..len]);
+
+    let mut previous_timestamp_us = 0;
+    for record in records {
+        let len = record
+            .encode(previous_timestamp_us, &mut buf)
+            .expect("A record always fits in its maximum size");
+        log.extend_from_slice(&buf[..len]);
+        previous_timestamp_us = record.timestamp_us;
+    }
+
+    log
+}
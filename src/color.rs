@@ -0,0 +1,410 @@
+//! Color correction applied to the colors sent by the host before they
+//! reach the LEDs.
+//!
+//! The host picks colors for the LEDs of the original device, which
+//! don't look the same on most WS2812 strips: colors look washed out
+//! and skew blue. [`ColorPipeline`] corrects them, per channel, by
+//! scaling them with a white balance and a global brightness, and then
+//! mapping them through a gamma correction table.
+//!
+//! Strips also differ in the order they expect the components in, and
+//! some have a white LED. Every channel has a [`ColorOrder`], used by
+//! [`ColorPipeline::encode`] to write the corrected colors as the raw
+//! bytes of the strip. Drivers from smart-leds already order the
+//! components themselves, so this is meant for drivers that take raw
+//! bytes, like SPI or PIO ones.
+
+use crate::aura::RGB8;
+use crate::pixel::{scale, scale8};
+use crate::RogTerminalMessage;
+
+/// A lookup table that maps every channel value to its gamma corrected
+/// value.
+pub type GammaTable = [u8; 256];
+
+/// A table that leaves the values untouched.
+pub const GAMMA_LINEAR: GammaTable = gamma_table(1.0);
+
+/// The gamma of sRGB displays.
+pub const GAMMA_2_2: GammaTable = gamma_table(2.2);
+
+/// A gamma that usually looks right on WS2812 strips.
+pub const GAMMA_2_8: GammaTable = gamma_table(2.8);
+
+/// Natural logarithm of `x`, for `x > 0`. The argument is reduced to
+/// [0.5, 1) so the series converges quickly.
+const fn ln(mut x: f64) -> f64 {
+    const LN_2: f64 = core::f64::consts::LN_2;
+
+    let mut result = 0.0;
+    while x >= 1.0 {
+        x /= 2.0;
+        result += LN_2;
+    }
+    while x < 0.5 {
+        x *= 2.0;
+        result -= LN_2;
+    }
+
+    // ln(x) = 2 * atanh((x - 1) / (x + 1))
+    let z = (x - 1.0) / (x + 1.0);
+    let z2 = z * z;
+    let mut term = z;
+    let mut sum = 0.0;
+    let mut k = 0;
+    while k < 20 {
+        sum += term / (2 * k + 1) as f64;
+        term *= z2;
+        k += 1;
+    }
+
+    result + 2.0 * sum
+}
+
+/// Exponential of `x`. The argument is scaled down by 2^8 so the series
+/// converges quickly, and the result is squared back up.
+const fn exp(x: f64) -> f64 {
+    let y = x / 256.0;
+    let mut term = 1.0;
+    let mut sum = 1.0;
+    let mut k = 1;
+    while k < 12 {
+        term *= y / k as f64;
+        sum += term;
+        k += 1;
+    }
+
+    let mut i = 0;
+    while i < 8 {
+        sum *= sum;
+        i += 1;
+    }
+
+    sum
+}
+
+/// Builds a gamma correction table at compile time, where `out = in ^
+/// gamma` with both values normalized to [0, 1].
+pub const fn gamma_table(gamma: f32) -> GammaTable {
+    let mut table = [0; 256];
+    let mut i = 1;
+    while i < 256 {
+        let normalized = i as f64 / 255.0;
+        let corrected = exp(gamma as f64 * ln(normalized));
+        table[i] = (corrected * 255.0 + 0.5) as u8;
+        i += 1;
+    }
+
+    table
+}
+
+/// Per-component scaling factors, where 255 leaves the component
+/// untouched.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WhiteBalance {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl WhiteBalance {
+    /// A white balance that leaves the colors untouched.
+    pub const NEUTRAL: Self = Self::new(255, 255, 255);
+
+    /// A white balance that tames the blue tint of WS2812 strips.
+    pub const WS2812: Self = Self::new(255, 176, 240);
+
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b }
+    }
+}
+
+impl Default for WhiteBalance {
+    fn default() -> Self {
+        Self::NEUTRAL
+    }
+}
+
+/// How the white component of RGBW strips is taken from an RGB color.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum WhiteExtraction {
+    /// The white LED is never lit.
+    None,
+
+    /// The white LED is lit with the common part of the three
+    /// components, which is removed from them. Keeps the colors as
+    /// close as possible to the ones of an RGB strip.
+    #[default]
+    Subtract,
+
+    /// The white LED is lit with the common part of the three
+    /// components, which are left as they are. Brighter, but whites
+    /// and pastel colors look washed out.
+    Add,
+}
+
+impl WhiteExtraction {
+    /// Splits a color into its RGB and white components.
+    pub const fn extract(&self, color: RGB8) -> (RGB8, u8) {
+        let mut w = color.r;
+        if color.g < w {
+            w = color.g;
+        }
+        if color.b < w {
+            w = color.b;
+        }
+
+        match self {
+            WhiteExtraction::None => (color, 0),
+            WhiteExtraction::Subtract => (
+                RGB8 {
+                    r: color.r - w,
+                    g: color.g - w,
+                    b: color.b - w,
+                },
+                w,
+            ),
+            WhiteExtraction::Add => (color, w),
+        }
+    }
+}
+
+/// The order a strip expects the components of every LED in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ColorOrder {
+    #[default]
+    Rgb,
+    Rbg,
+
+    /// Used by WS2812 strips.
+    Grb,
+    Gbr,
+
+    /// Used by some 12V strips.
+    Brg,
+    Bgr,
+    Rgbw(WhiteExtraction),
+
+    /// Used by SK6812 RGBW strips.
+    Grbw(WhiteExtraction),
+}
+
+impl ColorOrder {
+    /// Returns the number of bytes of every LED.
+    pub const fn bytes_per_led(&self) -> usize {
+        match self {
+            ColorOrder::Rgbw(_) | ColorOrder::Grbw(_) => 4,
+            _ => 3,
+        }
+    }
+
+    /// Returns the bytes of a single LED. Only the first
+    /// [`ColorOrder::bytes_per_led`] bytes are meaningful.
+    pub const fn to_bytes(&self, color: RGB8) -> [u8; 4] {
+        let RGB8 { r, g, b } = color;
+        match self {
+            ColorOrder::Rgb => [r, g, b, 0],
+            ColorOrder::Rbg => [r, b, g, 0],
+            ColorOrder::Grb => [g, r, b, 0],
+            ColorOrder::Gbr => [g, b, r, 0],
+            ColorOrder::Brg => [b, r, g, 0],
+            ColorOrder::Bgr => [b, g, r, 0],
+            ColorOrder::Rgbw(white) => {
+                let (RGB8 { r, g, b }, w) = white.extract(color);
+                [r, g, b, w]
+            }
+            ColorOrder::Grbw(white) => {
+                let (RGB8 { r, g, b }, w) = white.extract(color);
+                [g, r, b, w]
+            }
+        }
+    }
+
+    /// Writes the bytes of as many of `colors` as fit in `out`, and
+    /// returns the number of bytes written.
+    pub fn encode(&self, colors: &[RGB8], out: &mut [u8]) -> usize {
+        let size = self.bytes_per_led();
+        let mut written = 0;
+        for (color, chunk) in colors.iter().zip(out.chunks_exact_mut(size)) {
+            chunk.copy_from_slice(&self.to_bytes(*color)[..size]);
+            written += size;
+        }
+
+        written
+    }
+}
+
+/// The color correction of a single channel.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ChannelColorConfig {
+    pub gamma: &'static GammaTable,
+    pub white_balance: WhiteBalance,
+    pub order: ColorOrder,
+}
+
+impl ChannelColorConfig {
+    /// A configuration that leaves the colors untouched.
+    pub const NEUTRAL: Self = Self::new(&GAMMA_LINEAR, WhiteBalance::NEUTRAL);
+
+    pub const fn new(gamma: &'static GammaTable, white_balance: WhiteBalance) -> Self {
+        Self {
+            gamma,
+            white_balance,
+            order: ColorOrder::Rgb,
+        }
+    }
+
+    pub const fn with_order(mut self, order: ColorOrder) -> Self {
+        self.order = order;
+        self
+    }
+}
+
+impl Default for ChannelColorConfig {
+    fn default() -> Self {
+        Self::NEUTRAL
+    }
+}
+
+/// Color correction for `CHANNELS` channels.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ColorPipeline<const CHANNELS: usize> {
+    channels: [ChannelColorConfig; CHANNELS],
+    brightness: u8,
+}
+
+impl<const CHANNELS: usize> ColorPipeline<CHANNELS> {
+    /// Creates a pipeline that applies the same correction to every
+    /// channel, at full brightness.
+    pub const fn new(config: ChannelColorConfig) -> Self {
+        Self {
+            channels: [config; CHANNELS],
+            brightness: 255,
+        }
+    }
+
+    /// Changes the correction of a single channel.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the channel is out of range.
+    pub const fn with_channel(mut self, channel: u8, config: ChannelColorConfig) -> Self {
+        self.channels[channel as usize] = config;
+        self
+    }
+
+    pub const fn with_brightness(mut self, brightness: u8) -> Self {
+        self.brightness = brightness;
+        self
+    }
+
+    pub fn channel(&self, channel: u8) -> Option<&ChannelColorConfig> {
+        self.channels.get(channel as usize)
+    }
+
+    pub fn channel_mut(&mut self, channel: u8) -> Option<&mut ChannelColorConfig> {
+        self.channels.get_mut(channel as usize)
+    }
+
+    pub fn brightness(&self) -> u8 {
+        self.brightness
+    }
+
+    /// Sets the brightness of every channel, where 255 is the full
+    /// brightness.
+    pub fn set_brightness(&mut self, brightness: u8) {
+        self.brightness = brightness;
+    }
+
+    pub fn set_gamma(&mut self, channel: u8, gamma: &'static GammaTable) {
+        if let Some(config) = self.channels.get_mut(channel as usize) {
+            config.gamma = gamma;
+        }
+    }
+
+    pub fn set_white_balance(&mut self, channel: u8, white_balance: WhiteBalance) {
+        if let Some(config) = self.channels.get_mut(channel as usize) {
+            config.white_balance = white_balance;
+        }
+    }
+
+    pub fn set_order(&mut self, channel: u8, order: ColorOrder) {
+        if let Some(config) = self.channels.get_mut(channel as usize) {
+            config.order = order;
+        }
+    }
+
+    /// Returns the color order of a channel. Unknown channels are RGB.
+    pub fn order(&self, channel: u8) -> ColorOrder {
+        self.channel(channel)
+            .map(|config| config.order)
+            .unwrap_or_default()
+    }
+
+    /// Corrects a single color for a channel. Colors of unknown channels
+    /// are only scaled by the brightness.
+    pub fn process_color(&self, channel: u8, color: RGB8) -> RGB8 {
+        let Some(config) = self.channels.get(channel as usize) else {
+            return scale(color, self.brightness);
+        };
+
+        let wb = config.white_balance;
+        RGB8 {
+            r: config.gamma[scale8(scale8(color.r, wb.r), self.brightness) as usize],
+            g: config.gamma[scale8(scale8(color.g, wb.g), self.brightness) as usize],
+            b: config.gamma[scale8(scale8(color.b, wb.b), self.brightness) as usize],
+        }
+    }
+
+    /// Corrects the colors of a channel in place.
+    pub fn process(&self, channel: u8, leds: &mut [RGB8]) {
+        for led in leds.iter_mut() {
+            *led = self.process_color(channel, *led);
+        }
+    }
+
+    /// Corrects the colors of a channel into another buffer, like when
+    /// taking them from the front buffer of a
+    /// [`crate::framebuffer::AuraFramebuffer`]. Only as many colors as
+    /// fit in both buffers are processed.
+    pub fn process_into(&self, channel: u8, src: &[RGB8], dst: &mut [RGB8]) {
+        for (out, color) in dst.iter_mut().zip(src) {
+            *out = self.process_color(channel, *color);
+        }
+    }
+
+    /// Corrects the colors of a channel, and writes them into `out` as
+    /// the raw bytes of the strip, in the color order of the channel.
+    /// Writes as many LEDs as fit, and returns the number of bytes
+    /// written.
+    pub fn encode(&self, channel: u8, colors: &[RGB8], out: &mut [u8]) -> usize {
+        let order = self.order(channel);
+        let mut written = 0;
+        for color in colors {
+            let color = self.process_color(channel, *color);
+            match order.encode(&[color], &mut out[written..]) {
+                0 => break,
+                len => written += len,
+            }
+        }
+
+        written
+    }
+
+    /// Corrects the colors of a [`RogTerminalMessage::UpdateLeds`]
+    /// message in place. Any other message is left untouched.
+    pub fn process_message(&self, message: &mut RogTerminalMessage) {
+        if let RogTerminalMessage::UpdateLeds {
+            channel, led_data, ..
+        } = message
+        {
+            self.process(channel.index(), led_data);
+        }
+    }
+}
+
+impl<const CHANNELS: usize> Default for ColorPipeline<CHANNELS> {
+    fn default() -> Self {
+        Self::new(ChannelColorConfig::NEUTRAL)
+    }
+}
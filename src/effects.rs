@@ -0,0 +1,254 @@
+//! Local rendering of the preset Aura effects.
+//!
+//! When the host selects a preset effect, the original device animates
+//! it on its own. [`AuraEffectRenderer`] reproduces those animations in
+//! software, so an application only has to call
+//! [`AuraEffectRenderer::render`] periodically with a monotonic tick,
+//! and push the result to the LEDs. Everything is integer math, with no
+//! allocations.
+//!
+//! The animations are approximations of the ones of the original
+//! device, not exact replicas.
+
+use crate::aura::{AuraEffect, AuraEffectDirection, AuraEffectParams, RGB8};
+use crate::pixel::{scale, scale8, BLACK};
+
+/// The period of a full animation cycle at the middle speed, in ticks.
+/// Ticks are expected to be milliseconds, but any monotonic unit works
+/// by just scaling the speed.
+const BASE_PERIOD: u32 = 2000;
+
+/// The time a random flicker LED keeps its brightness, in ticks.
+const FLICKER_STEP: u32 = 80;
+
+/// The length, in LEDs, of the fading tail of the chase effects.
+const CHASE_TAIL: usize = 6;
+
+/// The spacing between lit LEDs on the non-fading chase effects.
+const CHASE_SPACING: usize = 3;
+
+/// Renders a preset [`AuraEffect`] for a channel.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AuraEffectRenderer {
+    effect: AuraEffect,
+    params: AuraEffectParams,
+    led_count: usize,
+}
+
+impl AuraEffectRenderer {
+    pub fn new(effect: AuraEffect, params: AuraEffectParams, led_count: usize) -> Self {
+        Self {
+            effect,
+            params,
+            led_count,
+        }
+    }
+
+    pub fn effect(&self) -> AuraEffect {
+        self.effect
+    }
+
+    pub fn params(&self) -> &AuraEffectParams {
+        &self.params
+    }
+
+    pub fn led_count(&self) -> usize {
+        self.led_count
+    }
+
+    /// Changes the effect being rendered, usually after receiving a
+    /// [`crate::RogTerminalMessage::SetEffect`].
+    pub fn set_effect(&mut self, effect: AuraEffect, params: AuraEffectParams) {
+        self.effect = effect;
+        self.params = params;
+    }
+
+    pub fn set_led_count(&mut self, led_count: usize) {
+        self.led_count = led_count;
+    }
+
+    /// Renders the effect at the given tick into `leds`. Only the first
+    /// `led_count` LEDs are written, and the rest are turned off.
+    ///
+    /// [`AuraEffect::Direct`] leaves `leds` untouched, since the colors
+    /// are the ones set by the host.
+    pub fn render(&self, tick: u32, leds: &mut [RGB8]) {
+        if self.effect == AuraEffect::Direct {
+            return;
+        }
+
+        let count = self.led_count.min(leds.len());
+        let (strip, rest) = leds.split_at_mut(count);
+        rest.fill(BLACK);
+        render(self.effect, &self.params, tick, strip);
+    }
+}
+
+/// Renders `effect` at the given tick into every LED of `leds`.
+/// [`AuraEffect::Direct`] leaves `leds` untouched.
+pub fn render(effect: AuraEffect, params: &AuraEffectParams, tick: u32, leds: &mut [RGB8]) {
+    let period = period(params.speed);
+    let cycle = phase(tick, period);
+    let count = leds.len();
+
+    match effect {
+        AuraEffect::Direct => return,
+        AuraEffect::Off => leds.fill(BLACK),
+        AuraEffect::Static | AuraEffect::Music => leds.fill(params.color),
+        AuraEffect::Breathing => leds.fill(scale(params.color, breath(cycle))),
+        AuraEffect::Flashing => leds.fill(if cycle < 64 { params.color } else { BLACK }),
+        AuraEffect::SpectrumCycle => leds.fill(wheel(cycle)),
+        AuraEffect::SpectrumCycleBreathing => {
+            let hue = phase(tick, period * 8);
+            leds.fill(scale(wheel(hue), breath(cycle)))
+        }
+        AuraEffect::Rainbow => {
+            for (i, led) in leds.iter_mut().enumerate() {
+                let pos = position(i, count, params.direction);
+                *led = wheel(cycle.wrapping_add((pos * 256 / count.max(1)) as u8));
+            }
+        }
+        AuraEffect::SpectrumCycleWave => {
+            for (i, led) in leds.iter_mut().enumerate() {
+                let pos = position(i, count, params.direction);
+                *led = wheel(cycle.wrapping_add((pos * 64 / count.max(1)) as u8));
+            }
+        }
+        AuraEffect::ChaseFade | AuraEffect::SpectrumCycleChaseFade => {
+            let head = cycle as usize * (count + CHASE_TAIL) / 256;
+            let color = if effect == AuraEffect::ChaseFade {
+                params.color
+            } else {
+                wheel(phase(tick, period * 4))
+            };
+
+            for (i, led) in leds.iter_mut().enumerate() {
+                let pos = position(i, count, params.direction);
+                *led = match head.checked_sub(pos) {
+                    Some(distance) if distance < CHASE_TAIL => {
+                        let level = 255 - (distance * 255 / CHASE_TAIL) as u8;
+                        blend(params.secondary_color, color, level)
+                    }
+                    _ => params.secondary_color,
+                };
+            }
+        }
+        AuraEffect::Chase | AuraEffect::SpectrumCycleChase => {
+            let step = cycle as usize * CHASE_SPACING / 256;
+            let color = if effect == AuraEffect::Chase {
+                params.color
+            } else {
+                wheel(phase(tick, period * 4))
+            };
+
+            for (i, led) in leds.iter_mut().enumerate() {
+                let pos = position(i, count, params.direction);
+                *led = if (pos + CHASE_SPACING - step).is_multiple_of(CHASE_SPACING) {
+                    color
+                } else {
+                    params.secondary_color
+                };
+            }
+        }
+        AuraEffect::ChaseRainbowPulse => {
+            let head = cycle as usize * (count + CHASE_TAIL) / 256;
+            for (i, led) in leds.iter_mut().enumerate() {
+                let pos = position(i, count, params.direction);
+                *led = match head.checked_sub(pos) {
+                    Some(distance) if distance < CHASE_TAIL => {
+                        let level = 255 - (distance * 255 / CHASE_TAIL) as u8;
+                        scale(wheel((pos * 256 / count.max(1)) as u8), level)
+                    }
+                    _ => BLACK,
+                };
+            }
+        }
+        AuraEffect::RandomFlicker => {
+            let step = tick / (FLICKER_STEP * 512 / (params.speed as u32 + 128));
+            for (i, led) in leds.iter_mut().enumerate() {
+                let level = hash(step.wrapping_mul(0x9e37_79b9) ^ i as u32) as u8;
+                *led = scale(params.color, level);
+            }
+        }
+    }
+
+    if params.brightness != 0xff {
+        for led in leds.iter_mut() {
+            *led = scale(*led, params.brightness);
+        }
+    }
+}
+
+/// Returns the duration of a full animation cycle for a given speed.
+/// The slowest speed is about three times slower than the fastest one.
+fn period(speed: u8) -> u32 {
+    BASE_PERIOD * 128 / (speed as u32 + 128) * 2
+}
+
+/// Returns the position within the animation cycle, where 256 would be
+/// a full cycle.
+fn phase(tick: u32, period: u32) -> u8 {
+    ((tick % period) as u64 * 256 / period as u64) as u8
+}
+
+/// Returns the position of an LED along the animation direction.
+fn position(index: usize, count: usize, direction: AuraEffectDirection) -> usize {
+    match direction {
+        AuraEffectDirection::Forward => index,
+        AuraEffectDirection::Reverse => count - 1 - index,
+    }
+}
+
+/// Returns the brightness of a breathing cycle: a triangle wave,
+/// squared so it lingers longer near dark, like the original device.
+fn breath(phase: u8) -> u8 {
+    let triangle = if phase < 128 {
+        phase as u16 * 2
+    } else {
+        (255 - phase) as u16 * 2
+    };
+    ((triangle * triangle) / 255) as u8
+}
+
+/// Blends from `from` to `to`, where 255 returns `to`.
+fn blend(from: RGB8, to: RGB8, level: u8) -> RGB8 {
+    let inverse = 255 - level;
+    RGB8 {
+        r: scale8(from.r, inverse) + scale8(to.r, level),
+        g: scale8(from.g, inverse) + scale8(to.g, level),
+        b: scale8(from.b, inverse) + scale8(to.b, level),
+    }
+}
+
+/// Returns a fully saturated color from the color wheel.
+fn wheel(hue: u8) -> RGB8 {
+    let sector = hue / 85;
+    let offset = (hue % 85) * 3;
+    match sector {
+        0 => RGB8 {
+            r: 255 - offset,
+            g: offset,
+            b: 0,
+        },
+        1 => RGB8 {
+            r: 0,
+            g: 255 - offset,
+            b: offset,
+        },
+        _ => RGB8 {
+            r: offset,
+            g: 0,
+            b: 255 - offset,
+        },
+    }
+}
+
+/// A cheap integer hash, good enough for flickering LEDs.
+fn hash(mut x: u32) -> u32 {
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x7feb_352d);
+    x ^= x >> 15;
+    x = x.wrapping_mul(0x846c_a68b);
+    x ^= x >> 16;
+    x
+}
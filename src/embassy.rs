@@ -0,0 +1,359 @@
+//! Backend on top of [embassy-usb](https://docs.rs/embassy-usb), with
+//! an async API.
+//!
+//! The reports of the host arrive through SET_REPORT requests, which
+//! embassy-usb hands to a request handler while it processes the
+//! control pipe. [`AuraEmbassyState`] holds the
+//! [`AuraProtocolEngine`] behind a mutex, so the handler can feed it
+//! reports while the application awaits the resulting messages, and a
+//! responder sends the responses through the interrupt IN endpoint.
+//!
+//! ```ignore
+//! let profile = DeviceProfile::ROG_TERMINAL;
+//! let mut builder = Builder::new(driver, profile.embassy_config(), ...);
+//! let state = STATE.init(AuraEmbassyState::<CriticalSectionRawMutex>::new(
+//!     AuraProtocolEngine::from_profile(&profile),
+//! ));
+//! let aura = AuraEmbassyClass::new(&mut builder, state, &profile);
+//! let mut usb = builder.build();
+//! let (messages, mut responder) = aura.split();
+//!
+//! join3(usb.run(), responder.run(), async {
+//!     loop {
+//!         match messages.next_message().await {
+//!             RogTerminalMessage::UpdateLeds { .. } => { ... }
+//!             _ => {}
+//!         }
+//!     }
+//! })
+//! .await;
+//! ```
+
+use core::cell::RefCell;
+
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::signal::Signal;
+use embassy_usb::class::hid::{
+    Config, HidBootProtocol, HidSubclass, HidWriter, ReportId, RequestHandler, State,
+};
+use embassy_usb::control::OutResponse;
+use embassy_usb::driver::{Driver, EndpointError};
+use embassy_usb::Builder;
+use usb_device::UsbError;
+
+use crate::aura::constants::{AURA_HID_REPORT_ID, AURA_INPUT_REPORT_SIZE, AURA_OUTPUT_REPORT_SIZE};
+use crate::engine::AuraProtocolEngine;
+use crate::profile::{DeviceProfile, HID_POLL_INTERVAL_MS};
+use crate::stats::AuraError;
+use crate::{RogTerminalMessage, DEFAULT_MESSAGE_QUEUE_CAPACITY};
+
+/// The part of the state shared between the request handler, the
+/// message receiver and the responder.
+struct Shared<'d, M: RawMutex, const Q: usize> {
+    engine: Mutex<M, RefCell<AuraProtocolEngine<'d, Q>>>,
+    messages: Signal<M, ()>,
+    responses: Signal<M, ()>,
+}
+
+impl<'d, M: RawMutex, const Q: usize> Shared<'d, M, Q> {
+    fn lock<R>(&self, f: impl FnOnce(&mut AuraProtocolEngine<'d, Q>) -> R) -> R {
+        self.engine.lock(|engine| f(&mut engine.borrow_mut()))
+    }
+}
+
+/// Receives the SET_REPORT requests of the host.
+struct AuraRequestHandler<'d, M: RawMutex, const Q: usize> {
+    shared: &'d Shared<'d, M, Q>,
+}
+
+impl<M: RawMutex, const Q: usize> RequestHandler for AuraRequestHandler<'_, M, Q> {
+    fn set_report(&mut self, id: ReportId, data: &[u8]) -> OutResponse {
+        if id != ReportId::Out(AURA_HID_REPORT_ID) || data.len() > AURA_OUTPUT_REPORT_SIZE {
+            return OutResponse::Rejected;
+        }
+
+        let (accepted, has_messages, has_responses) = self.shared.lock(|engine| {
+            if !engine.admit_report() {
+                return (false, true, false);
+            }
+
+            engine.handle_output_slice(data);
+            (
+                true,
+                engine.pending_messages() > 0,
+                engine.has_pending_responses(),
+            )
+        });
+
+        if has_messages {
+            self.shared.messages.signal(());
+        }
+        if has_responses {
+            self.shared.responses.signal(());
+        }
+
+        if accepted {
+            OutResponse::Accepted
+        } else {
+            OutResponse::Rejected
+        }
+    }
+}
+
+/// The memory used by an [`AuraEmbassyClass`], which has to outlive
+/// the USB device, like the rest of the embassy-usb state.
+pub struct AuraEmbassyState<'d, M: RawMutex, const Q: usize = DEFAULT_MESSAGE_QUEUE_CAPACITY> {
+    shared: Shared<'d, M, Q>,
+    hid: State<'d>,
+    handler: Option<AuraRequestHandler<'d, M, Q>>,
+}
+
+impl<'d, M: RawMutex, const Q: usize> AuraEmbassyState<'d, M, Q> {
+    pub fn new(engine: AuraProtocolEngine<'d, Q>) -> Self {
+        Self {
+            shared: Shared {
+                engine: Mutex::new(RefCell::new(engine)),
+                messages: Signal::new(),
+                responses: Signal::new(),
+            },
+            hid: State::new(),
+            handler: None,
+        }
+    }
+}
+
+/// The Aura protocol over embassy-usb.
+pub struct AuraEmbassyClass<'d, D: Driver<'d>, M: RawMutex, const Q: usize> {
+    writer: HidWriter<'d, D, AURA_INPUT_REPORT_SIZE>,
+    shared: &'d Shared<'d, M, Q>,
+}
+
+impl<'d, D: Driver<'d>, M: RawMutex, const Q: usize> AuraEmbassyClass<'d, D, M, Q> {
+    /// Adds the HID interface, with the descriptor of the profile, to
+    /// the device being built.
+    pub fn new(
+        builder: &mut Builder<'d, D>,
+        state: &'d mut AuraEmbassyState<'d, M, Q>,
+        profile: &DeviceProfile,
+    ) -> Self {
+        let AuraEmbassyState {
+            shared,
+            hid,
+            handler,
+        } = state;
+        let shared: &'d Shared<'d, M, Q> = shared;
+        let handler = handler.insert(AuraRequestHandler { shared });
+
+        let config = Config {
+            report_descriptor: profile.hid_descriptor,
+            request_handler: Some(handler),
+            poll_ms: HID_POLL_INTERVAL_MS,
+            max_packet_size: AURA_INPUT_REPORT_SIZE as u16,
+            hid_subclass: HidSubclass::No,
+            hid_boot_protocol: HidBootProtocol::None,
+        };
+
+        Self {
+            writer: HidWriter::new(builder, hid, config),
+            shared,
+        }
+    }
+
+    /// Splits the class into a receiver of messages and the responder,
+    /// which have to be run concurrently.
+    pub fn split(self) -> (AuraMessageReceiver<'d, M, Q>, AuraResponder<'d, D, M, Q>) {
+        (
+            AuraMessageReceiver {
+                shared: self.shared,
+            },
+            AuraResponder {
+                writer: self.writer,
+                shared: self.shared,
+            },
+        )
+    }
+}
+
+/// Hands the messages decoded from the reports of the host to the
+/// application.
+#[derive(Clone, Copy)]
+pub struct AuraMessageReceiver<'d, M: RawMutex, const Q: usize> {
+    shared: &'d Shared<'d, M, Q>,
+}
+
+impl<'d, M: RawMutex, const Q: usize> AuraMessageReceiver<'d, M, Q> {
+    /// Waits for the next message from the host.
+    pub async fn next_message(&self) -> RogTerminalMessage {
+        loop {
+            if let Some(message) = self.try_next_message() {
+                return message;
+            }
+
+            self.shared.messages.wait().await;
+        }
+    }
+
+    /// Takes the next message from the host, if any.
+    pub fn try_next_message(&self) -> Option<RogTerminalMessage> {
+        self.shared.lock(|engine| engine.poll_next_message())
+    }
+
+    /// Runs a closure with the engine, like for reading its statistics
+    /// or changing its configuration.
+    pub fn with_engine<R>(&self, f: impl FnOnce(&mut AuraProtocolEngine<'d, Q>) -> R) -> R {
+        let result = self.shared.lock(f);
+        // The closure may have queued a response.
+        self.shared.responses.signal(());
+        result
+    }
+}
+
+/// Sends the responses of the engine to the host.
+pub struct AuraResponder<'d, D: Driver<'d>, M: RawMutex, const Q: usize> {
+    writer: HidWriter<'d, D, AURA_INPUT_REPORT_SIZE>,
+    shared: &'d Shared<'d, M, Q>,
+}
+
+impl<'d, D: Driver<'d>, M: RawMutex, const Q: usize> AuraResponder<'d, D, M, Q> {
+    /// Sends the responses as the engine queues them. Never returns.
+    pub async fn run(&mut self) -> ! {
+        loop {
+            self.writer.ready().await;
+
+            while let Some(report) = self.shared.lock(|engine| engine.peek_response()) {
+                match self.writer.write(&report).await {
+                    Ok(()) => {}
+                    // The device was disconnected or reset. Keep the
+                    // response for when it's configured again.
+                    Err(EndpointError::Disabled) => break,
+                    Err(EndpointError::BufferOverflow) => {
+                        self.shared.lock(|engine| {
+                            engine
+                                .record_error(AuraError::InputPushFailed(UsbError::BufferOverflow))
+                        });
+                    }
+                }
+                self.shared.lock(|engine| engine.pop_response());
+            }
+
+            if self.shared.lock(|engine| !engine.has_pending_responses()) {
+                self.shared.responses.wait().await;
+            }
+        }
+    }
+}
+
+// Unlike the rest of the crate, tested from the inside: the request
+// handler is private, and embassy-usb only calls it from its control
+// pipe, which tests/ could only drive through a fake embassy-usb
+// driver. These also check the signals of the private `Shared` state,
+// which are what wake the receiver and the responder up.
+#[cfg(test)]
+mod tests {
+    use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+
+    use super::*;
+    use crate::aura::AuraOutputRequest;
+    use crate::QueueOverflowPolicy;
+
+    fn shared<'d>(engine: AuraProtocolEngine<'d, 1>) -> Shared<'d, NoopRawMutex, 1> {
+        Shared {
+            engine: Mutex::new(RefCell::new(engine)),
+            messages: Signal::new(),
+            responses: Signal::new(),
+        }
+    }
+
+    fn engine<'d>() -> AuraProtocolEngine<'d, 1> {
+        AuraProtocolEngine::from_profile(&DeviceProfile::ROG_TERMINAL).with_queue_capacity()
+    }
+
+    const COMMIT: &[u8] = &[AURA_HID_REPORT_ID, 0x3f, 0x55];
+
+    #[test]
+    fn rejects_foreign_and_oversized_reports() {
+        let shared = shared(engine());
+        let mut handler = AuraRequestHandler { shared: &shared };
+
+        assert_eq!(
+            handler.set_report(ReportId::Out(0x01), COMMIT),
+            OutResponse::Rejected
+        );
+        assert_eq!(
+            handler.set_report(ReportId::Feature(AURA_HID_REPORT_ID), COMMIT),
+            OutResponse::Rejected
+        );
+
+        let mut oversized = [0; AURA_OUTPUT_REPORT_SIZE + 1];
+        oversized[..COMMIT.len()].copy_from_slice(COMMIT);
+        assert_eq!(
+            handler.set_report(ReportId::Out(AURA_HID_REPORT_ID), &oversized),
+            OutResponse::Rejected
+        );
+
+        assert!(!shared.messages.signaled());
+        assert_eq!(
+            shared.lock(|engine| engine.stats().total_reports_received()),
+            0
+        );
+    }
+
+    #[test]
+    fn refuses_reports_while_the_queue_is_full() {
+        let shared = shared(engine().with_overflow_policy(QueueOverflowPolicy::Refuse));
+        let mut handler = AuraRequestHandler { shared: &shared };
+        let receiver = AuraMessageReceiver { shared: &shared };
+
+        assert_eq!(
+            handler.set_report(ReportId::Out(AURA_HID_REPORT_ID), COMMIT),
+            OutResponse::Accepted
+        );
+        assert!(shared.messages.signaled());
+        assert_eq!(
+            handler.set_report(ReportId::Out(AURA_HID_REPORT_ID), COMMIT),
+            OutResponse::Rejected
+        );
+        assert_eq!(
+            receiver.with_engine(|engine| engine.stats().refused_reports),
+            1
+        );
+
+        assert_eq!(
+            receiver.try_next_message(),
+            Some(RogTerminalMessage::Commit)
+        );
+        assert_eq!(receiver.try_next_message(), None);
+        assert_eq!(
+            handler.set_report(ReportId::Out(AURA_HID_REPORT_ID), COMMIT),
+            OutResponse::Accepted
+        );
+        assert_eq!(
+            receiver.try_next_message(),
+            Some(RogTerminalMessage::Commit)
+        );
+    }
+
+    #[test]
+    fn signals_responses() {
+        let shared = shared(engine());
+        let mut handler = AuraRequestHandler { shared: &shared };
+        let receiver = AuraMessageReceiver { shared: &shared };
+
+        let request = AuraOutputRequest::FirmwareVersion.encode();
+        assert_eq!(
+            handler.set_report(ReportId::Out(AURA_HID_REPORT_ID), &request),
+            OutResponse::Accepted
+        );
+        assert!(shared.responses.signaled());
+        assert!(!shared.messages.signaled());
+        assert_eq!(receiver.try_next_message(), None);
+
+        shared.responses.reset();
+        let pending = receiver.with_engine(|engine| engine.has_pending_responses());
+        assert!(pending);
+        // The closure may have queued a response, so the responder is
+        // woken up anyway.
+        assert!(shared.responses.signaled());
+    }
+}
@@ -0,0 +1,500 @@
+//! The Aura protocol, independent of how reports reach the device.
+//!
+//! [`AuraProtocolEngine`] takes the raw 65 bytes output reports sent by
+//! the host, turns them into [`RogTerminalMessage`]s and queues the
+//! 64 bytes responses the host expects. It knows nothing about USB:
+//! transports only have to move reports in and out of it, which is all
+//! [`crate::AsusRogTerminalHidClass`] does over `usb-device`. The
+//! `embassy` and `uhid` backends, the session replayer and the host
+//! loopback do the same over their own transports, and so can a UART
+//! bridge:
+//!
+//! ```ignore
+//! let mut engine = AuraProtocolEngine::default();
+//! loop {
+//!     engine.handle_output_slice(uart.read_frame());
+//!     while let Some(response) = engine.pop_response() {
+//!         uart.write_frame(&response);
+//!     }
+//!     while let Some(message) = engine.poll_next_message() {
+//!         ...
+//!     }
+//! }
+//! ```
+
+use ringbuffer::{ConstGenericRingBuffer, RingBuffer};
+
+use crate::aura::constants::{
+    AURA_HID_REPORT_ID, AURA_MAX_DIRECT_LED_COUNT, AURA_OUTPUT_REPORT_SIZE,
+};
+use crate::aura::{
+    AuraChannel, AuraConfigTable, AuraInputReport, AuraInputResponse, AuraOutputReport,
+    AuraOutputReportType, AuraOutputRequest, InvalidReportError,
+};
+use crate::events::{AuraEventHandler, AuraHostQuery};
+use crate::identity::{DeviceIdentity, FirmwareVersion};
+use crate::profile::{AuraCommandSet, DeviceProfile};
+use crate::session::{AuraSessionRecorder, SessionDirection};
+use crate::stats::{AuraError, AuraStats};
+use crate::{
+    QueueOverflowPolicy, RawPassthrough, RawReportHandler, RogTerminalMessage,
+    DEFAULT_MESSAGE_QUEUE_CAPACITY, ROG_AURA_DEFAULT_FIRMWARE_VERSION,
+};
+
+/// The number of responses that can wait to be sent to the host.
+const RESPONSE_QUEUE_CAPACITY: usize = 4;
+
+enum RogTerminalReadyData {
+    FirmwareVersion,
+    ConfigTable,
+    Raw(AuraInputReport),
+}
+
+pub struct AuraProtocolEngine<'a, const Q: usize = DEFAULT_MESSAGE_QUEUE_CAPACITY> {
+    data_rdy: ConstGenericRingBuffer<RogTerminalReadyData, RESPONSE_QUEUE_CAPACITY>,
+    messages: ConstGenericRingBuffer<RogTerminalMessage, Q>,
+    overflow_policy: QueueOverflowPolicy,
+    stats: AuraStats,
+    firmware_version: FirmwareVersion,
+    config_table: AuraConfigTable,
+    supported_commands: AuraCommandSet,
+    raw_passthrough: RawPassthrough,
+    raw_handler: Option<&'a mut dyn RawReportHandler>,
+    event_handler: Option<&'a mut dyn AuraEventHandler>,
+    recorder: Option<&'a mut dyn AuraSessionRecorder>,
+}
+
+impl<'a> AuraProtocolEngine<'a> {
+    pub fn new(firmware_version: FirmwareVersion, config_table: AuraConfigTable) -> Self {
+        Self {
+            data_rdy: ConstGenericRingBuffer::new(),
+            messages: ConstGenericRingBuffer::new(),
+            overflow_policy: QueueOverflowPolicy::DropOldest,
+            stats: AuraStats::default(),
+            firmware_version,
+            config_table,
+            supported_commands: AuraCommandSet::ALL,
+            raw_passthrough: RawPassthrough::Off,
+            raw_handler: None,
+            event_handler: None,
+            recorder: None,
+        }
+    }
+
+    /// Creates an engine with the firmware version, config table and
+    /// supported commands of a profile.
+    pub fn from_profile(profile: &DeviceProfile) -> Self {
+        Self::new(*profile.firmware_version, profile.config_table)
+            .with_supported_commands(profile.supported_commands)
+    }
+}
+
+impl Default for AuraProtocolEngine<'_> {
+    fn default() -> Self {
+        Self::new(
+            *ROG_AURA_DEFAULT_FIRMWARE_VERSION,
+            AuraConfigTable::default(),
+        )
+    }
+}
+
+impl<'a, const Q: usize> AuraProtocolEngine<'a, Q> {
+    /// Changes the capacity of the message queue. Any message already
+    /// queued is discarded.
+    pub fn with_queue_capacity<const N: usize>(self) -> AuraProtocolEngine<'a, N> {
+        AuraProtocolEngine {
+            data_rdy: self.data_rdy,
+            messages: ConstGenericRingBuffer::new(),
+            overflow_policy: self.overflow_policy,
+            stats: self.stats,
+            firmware_version: self.firmware_version,
+            config_table: self.config_table,
+            supported_commands: self.supported_commands,
+            raw_passthrough: self.raw_passthrough,
+            raw_handler: self.raw_handler,
+            event_handler: self.event_handler,
+            recorder: self.recorder,
+        }
+    }
+
+    pub fn with_overflow_policy(mut self, policy: QueueOverflowPolicy) -> Self {
+        self.overflow_policy = policy;
+        self
+    }
+
+    pub fn overflow_policy(&self) -> QueueOverflowPolicy {
+        self.overflow_policy
+    }
+
+    pub fn set_overflow_policy(&mut self, policy: QueueOverflowPolicy) {
+        self.overflow_policy = policy;
+    }
+
+    /// Returns the number of messages discarded because the message
+    /// queue was full.
+    pub fn dropped_messages(&self) -> u32 {
+        self.stats.dropped_messages
+    }
+
+    /// Returns the protocol statistics since the class was created or
+    /// they were last reset.
+    pub fn stats(&self) -> &AuraStats {
+        &self.stats
+    }
+
+    pub fn reset_stats(&mut self) {
+        self.stats = AuraStats::default();
+    }
+
+    /// Returns the number of messages waiting to be polled.
+    pub fn pending_messages(&self) -> usize {
+        self.messages.len()
+    }
+
+    /// Changes the output reports understood by the device. Any other
+    /// report is ignored.
+    pub fn with_supported_commands(mut self, supported_commands: AuraCommandSet) -> Self {
+        self.supported_commands = supported_commands;
+        self
+    }
+
+    pub fn supported_commands(&self) -> AuraCommandSet {
+        self.supported_commands
+    }
+
+    pub fn firmware_version(&self) -> &FirmwareVersion {
+        &self.firmware_version
+    }
+
+    /// Changes the firmware version reported to the host from now on.
+    pub fn set_firmware_version(&mut self, firmware_version: FirmwareVersion) {
+        self.firmware_version = firmware_version;
+    }
+
+    /// Reports the firmware version of an identity to the host. The
+    /// string descriptors have to be set by the transport, like with
+    /// [`DeviceIdentity::apply`].
+    pub fn set_identity(&mut self, identity: &DeviceIdentity) {
+        self.firmware_version = *identity.firmware_version();
+    }
+
+    pub fn with_raw_passthrough(mut self, passthrough: RawPassthrough) -> Self {
+        self.raw_passthrough = passthrough;
+        self
+    }
+
+    pub fn raw_passthrough(&self) -> RawPassthrough {
+        self.raw_passthrough
+    }
+
+    pub fn set_raw_passthrough(&mut self, passthrough: RawPassthrough) {
+        self.raw_passthrough = passthrough;
+    }
+
+    /// Sends the reports surfaced by the [`RawPassthrough`] to a handler
+    /// instead of queueing them as messages. Does nothing while the
+    /// passthrough is off.
+    pub fn set_raw_handler(&mut self, handler: Option<&'a mut dyn RawReportHandler>) {
+        self.raw_handler = handler;
+    }
+
+    /// Invokes a handler for everything the host sends, as it is
+    /// received, instead of queueing messages. Messages already queued
+    /// are kept.
+    pub fn set_event_handler(&mut self, handler: Option<&'a mut dyn AuraEventHandler>) {
+        self.event_handler = handler;
+    }
+
+    /// Hands every report exchanged with the host to a recorder: the
+    /// output reports as they are handled, and the responses as they
+    /// are taken with [`AuraProtocolEngine::pop_response`].
+    pub fn set_recorder(&mut self, recorder: Option<&'a mut dyn AuraSessionRecorder>) {
+        self.recorder = recorder;
+    }
+
+    /// Queues a report to be sent to the host, like a response to a
+    /// [`RogTerminalMessage::Raw`] message.
+    pub fn queue_input_report(&mut self, report: AuraInputReport) {
+        self.enqueue_ready_data(RogTerminalReadyData::Raw(report));
+    }
+
+    pub fn config_table(&self) -> &AuraConfigTable {
+        &self.config_table
+    }
+
+    fn ready_report(&self, elem: &RogTerminalReadyData) -> AuraInputReport {
+        match elem {
+            RogTerminalReadyData::FirmwareVersion => {
+                AuraInputResponse::FirmwareVersion(self.firmware_version).encode()
+            }
+            RogTerminalReadyData::ConfigTable => {
+                AuraInputResponse::ConfigTable(self.config_table).encode()
+            }
+            RogTerminalReadyData::Raw(report) => *report,
+        }
+    }
+
+    /// Returns the next response to send to the host, without taking it
+    /// from the queue. Transports that may fail to send it should only
+    /// take it with [`AuraProtocolEngine::pop_response`] once sent.
+    pub fn peek_response(&self) -> Option<AuraInputReport> {
+        self.data_rdy.peek().map(|elem| self.ready_report(elem))
+    }
+
+    /// Takes the next response to send to the host.
+    pub fn pop_response(&mut self) -> Option<AuraInputReport> {
+        let elem = self.data_rdy.dequeue()?;
+        let report = self.ready_report(&elem);
+        if let Some(recorder) = self.recorder.as_mut() {
+            recorder.record(SessionDirection::Input, &report);
+        }
+        Some(report)
+    }
+
+    /// Whether there are responses waiting to be sent to the host.
+    pub fn has_pending_responses(&self) -> bool {
+        !self.data_rdy.is_empty()
+    }
+
+    /// Counts an error of the transport.
+    pub(crate) fn record_error(&mut self, error: AuraError) {
+        dev_error!("Transport error: {:?}", error);
+        self.stats.record_error(error);
+    }
+
+    fn enqueue_ready_data(&mut self, data: RogTerminalReadyData) {
+        if self.data_rdy.is_full() {
+            dev_error!("Response queue full, dropping oldest response");
+            self.stats.record_error(AuraError::ResponseQueueOverflow);
+        }
+
+        self.data_rdy.push(data);
+    }
+
+    /// Hands a report to the raw handler, or queues it as a message if
+    /// there is none.
+    fn pass_through(&mut self, report: &AuraOutputReport) {
+        match self.raw_handler.as_mut() {
+            Some(handler) => {
+                if let Some(response) = handler.handle_raw_report(report) {
+                    self.queue_input_report(response);
+                }
+            }
+            None => self.enqueue_message(RogTerminalMessage::Raw(*report)),
+        }
+    }
+
+    /// Handles an output report sent by the host, including its report
+    /// ID, as received through SET_REPORT requests or an interrupt OUT
+    /// endpoint.
+    pub fn handle_output_report(&mut self, report: &AuraOutputReport) {
+        dev_trace_report!("Report received", report);
+        if let Some(recorder) = self.recorder.as_mut() {
+            recorder.record(SessionDirection::Output, report);
+        }
+
+        if self.raw_passthrough == RawPassthrough::All && report[0] == AURA_HID_REPORT_ID {
+            self.pass_through(report);
+        }
+
+        // Unsupported commands are dropped before being decoded, so they
+        // are only counted as unsupported.
+        if let (AURA_HID_REPORT_ID, Ok(report_type)) =
+            (report[0], AuraOutputReportType::try_from(report[1]))
+        {
+            if !self.supported_commands.contains(report_type) {
+                dev_error!(
+                    "Ignoring unsupported report (type: {:02x})",
+                    report_type as u8
+                );
+                self.stats
+                    .record_error(AuraError::UnsupportedCommand(report_type as u8));
+                return;
+            }
+        }
+
+        let request = match AuraOutputRequest::decode(report) {
+            Err(InvalidReportError::LedCountOutOfRange) => {
+                dev_error!("Host sent a led count greater than maximum ({})", report[4]);
+                self.stats
+                    .record_error(AuraError::LedCountClamped(report[4]));
+                let mut clamped = *report;
+                clamped[4] = AURA_MAX_DIRECT_LED_COUNT;
+                AuraOutputRequest::decode(&clamped)
+            }
+            result => result,
+        };
+
+        let request = match request {
+            Ok(request) => request,
+            Err(InvalidReportError::InvalidReportType)
+                if self.raw_passthrough == RawPassthrough::Unknown =>
+            {
+                dev_info!("Passing through unknown report (type: {:02x})", report[1]);
+                self.pass_through(report);
+                return;
+            }
+            Err(e) => {
+                dev_error!(
+                    "Invalid report received (id: {:02x}, type: {:02x}): {:?}",
+                    report[0],
+                    report[1],
+                    e
+                );
+                self.stats.record_error(AuraError::InvalidReport(e));
+                return;
+            }
+        };
+
+        self.stats.count_report(request.report_type());
+
+        match request {
+            AuraOutputRequest::FirmwareVersion => {
+                dev_info!("Host requested firmware version");
+                if let Some(handler) = self.event_handler.as_mut() {
+                    handler.on_host_query(AuraHostQuery::FirmwareVersion);
+                }
+                self.enqueue_ready_data(RogTerminalReadyData::FirmwareVersion)
+            }
+            AuraOutputRequest::ConfigTable => {
+                dev_info!("Host requested device configuration table");
+                if let Some(handler) = self.event_handler.as_mut() {
+                    handler.on_host_query(AuraHostQuery::ConfigTable);
+                }
+                self.enqueue_ready_data(RogTerminalReadyData::ConfigTable)
+            }
+            AuraOutputRequest::SetEffect {
+                channel,
+                effect,
+                params,
+            } => {
+                dev_info!(
+                    "Host requested set effect for ch {} to {:?} (shutdown: {})",
+                    channel,
+                    effect,
+                    params.shutdown
+                );
+                self.enqueue_message(RogTerminalMessage::SetEffect {
+                    channel: self.config_table.resolve_channel(channel),
+                    effect,
+                    params,
+                })
+            }
+            AuraOutputRequest::SetMode {
+                channel,
+                effect,
+                shutdown,
+            } => {
+                dev_info!(
+                    "Host requested set mode for ch {} to {:?} (shutdown: {})",
+                    channel,
+                    effect,
+                    shutdown
+                );
+                self.enqueue_message(RogTerminalMessage::SetMode {
+                    channel: self.config_table.resolve_channel(channel),
+                    effect,
+                    shutdown,
+                })
+            }
+            AuraOutputRequest::SetEffectColors {
+                led_mask,
+                shutdown,
+                colors,
+            } => {
+                dev_info!("Host set effect colors with mask {:04x}", led_mask);
+                self.enqueue_message(RogTerminalMessage::SetEffectColors {
+                    led_mask,
+                    shutdown,
+                    colors,
+                })
+            }
+            AuraOutputRequest::Commit => {
+                dev_info!("Host requested commit");
+                self.enqueue_message(RogTerminalMessage::Commit)
+            }
+            AuraOutputRequest::SetDirectLeds {
+                channel,
+                offset,
+                apply,
+                mut led_data,
+            } => {
+                let channel = self.config_table.resolve_channel(channel);
+                if channel.is_logo() {
+                    // Anything beyond the single LED of the logo is
+                    // discarded.
+                    let len =
+                        (AuraChannel::LOGO_LED_COUNT as usize).saturating_sub(offset as usize);
+                    led_data.truncate(len);
+                }
+
+                self.enqueue_message(RogTerminalMessage::UpdateLeds {
+                    channel,
+                    offset,
+                    apply,
+                    led_data,
+                })
+            }
+        }
+    }
+
+    /// Like [`AuraProtocolEngine::handle_output_report`], for transports
+    /// that deliver reports without their trailing zeros. Bytes beyond
+    /// the size of a full report are ignored.
+    pub fn handle_output_slice(&mut self, data: &[u8]) {
+        let len = data.len().min(AURA_OUTPUT_REPORT_SIZE);
+        let mut report: AuraOutputReport = [0; AURA_OUTPUT_REPORT_SIZE];
+        report[..len].copy_from_slice(&data[..len]);
+        self.handle_output_report(&report)
+    }
+
+    fn enqueue_message(&mut self, message: RogTerminalMessage) {
+        if let Some(handler) = self.event_handler.as_mut() {
+            handler.handle_message(&message);
+            return;
+        }
+
+        if self.messages.is_full() {
+            self.stats.record_error(AuraError::MessageDropped);
+            if self.overflow_policy == QueueOverflowPolicy::DropNewest {
+                dev_error!("Message queue full, dropping newest message");
+                return;
+            }
+
+            // Under the Refuse policy no report should be pulled while the
+            // queue is full, so reaching this point means that the
+            // policy was changed with the queue already full. Just
+            // behave as DropOldest in that case.
+            dev_error!("Message queue full, dropping oldest message");
+        }
+
+        // Pushing into a full ring buffer overwrites its oldest element.
+        self.messages.push(message);
+    }
+
+    /// Whether the reports of the host should be refused for now, under
+    /// the [`QueueOverflowPolicy::Refuse`] policy. The transport is
+    /// expected to refuse them without handling them.
+    pub fn is_refusing_reports(&self) -> bool {
+        self.overflow_policy == QueueOverflowPolicy::Refuse && self.messages.is_full()
+    }
+
+    /// Called by transports that can fail a request, like SET_REPORT,
+    /// before taking a report from the host. Returns whether the report
+    /// may be taken; when not, the refusal has already been counted, and
+    /// the transport should fail the request without handling the
+    /// report.
+    pub fn admit_report(&mut self) -> bool {
+        if !self.is_refusing_reports() {
+            return true;
+        }
+
+        dev_error!("Message queue full, refusing report");
+        self.record_error(AuraError::ReportRefused);
+        false
+    }
+
+    pub fn poll_next_message(&mut self) -> Option<RogTerminalMessage> {
+        self.messages.dequeue()
+    }
+}
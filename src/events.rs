@@ -0,0 +1,100 @@
+//! Callback-based alternative to polling messages.
+//!
+//! Instead of calling
+//! [`AsusRogTerminalHidClass::poll_next_message`](crate::AsusRogTerminalHidClass::poll_next_message)
+//! after every poll, an [`AuraEventHandler`] can be set in the class,
+//! which invokes it synchronously from the data stage of the SET_REPORT
+//! request that carried the report, within [`usb_device::device::UsbDevice::poll`].
+//! The request is only acknowledged once the handler returns, so it
+//! should be quick. Messages are then never queued, which suits designs
+//! where the USB interrupt updates the LED state directly, like RTIC
+//! ones.
+
+use crate::aura::{AuraChannel, AuraEffect, AuraEffectParams, AuraOutputReport, RGB8};
+use crate::RogTerminalMessage;
+
+/// A request from the host for information about the device. The class
+/// answers it by itself, the handler is just notified.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AuraHostQuery {
+    FirmwareVersion,
+    ConfigTable,
+}
+
+/// Receives what the host sends, as it is received. Every method does
+/// nothing by default.
+pub trait AuraEventHandler {
+    /// The host set the colors of some LEDs of a channel, starting at
+    /// `offset`. `apply` is set on the last update of a frame.
+    fn on_direct_leds(&mut self, channel: AuraChannel, offset: u8, apply: bool, leds: &[RGB8]) {
+        let _ = (channel, offset, apply, leds);
+    }
+
+    /// The host set a preset effect in a channel.
+    fn on_effect(&mut self, channel: AuraChannel, effect: AuraEffect, params: &AuraEffectParams) {
+        let _ = (channel, effect, params);
+    }
+
+    /// The host set a preset effect in a channel through the generic
+    /// Aura USB request, which carries no colors. They come later
+    /// through [`AuraEventHandler::on_effect_colors`]. Forwards to
+    /// [`AuraEventHandler::on_effect`] with the default parameters by
+    /// default.
+    fn on_mode(&mut self, channel: AuraChannel, effect: AuraEffect, shutdown: bool) {
+        let params = AuraEffectParams {
+            shutdown,
+            ..Default::default()
+        };
+        self.on_effect(channel, effect, &params)
+    }
+
+    /// The host set the colors of the current effect for the LEDs whose
+    /// bit is set in `led_mask`.
+    fn on_effect_colors(&mut self, led_mask: u16, shutdown: bool, colors: &[RGB8]) {
+        let _ = (led_mask, shutdown, colors);
+    }
+
+    /// The host asked the device to persist its current state.
+    fn on_commit(&mut self) {}
+
+    /// The host asked for information about the device.
+    fn on_host_query(&mut self, query: AuraHostQuery) {
+        let _ = query;
+    }
+
+    /// The host sent a report surfaced by the
+    /// [`RawPassthrough`](crate::RawPassthrough) of the class.
+    fn on_raw_report(&mut self, report: &AuraOutputReport) {
+        let _ = report;
+    }
+
+    /// Invokes the method matching a message. Handy to reuse a handler
+    /// with messages taken from the queue.
+    fn handle_message(&mut self, message: &RogTerminalMessage) {
+        match message {
+            RogTerminalMessage::UpdateLeds {
+                channel,
+                offset,
+                apply,
+                led_data,
+            } => self.on_direct_leds(*channel, *offset, *apply, led_data),
+            RogTerminalMessage::SetEffect {
+                channel,
+                effect,
+                params,
+            } => self.on_effect(*channel, *effect, params),
+            RogTerminalMessage::SetMode {
+                channel,
+                effect,
+                shutdown,
+            } => self.on_mode(*channel, *effect, *shutdown),
+            RogTerminalMessage::SetEffectColors {
+                led_mask,
+                shutdown,
+                colors,
+            } => self.on_effect_colors(*led_mask, *shutdown, colors),
+            RogTerminalMessage::Commit => self.on_commit(),
+            RogTerminalMessage::Raw(report) => self.on_raw_report(report),
+        }
+    }
+}
@@ -0,0 +1,125 @@
+//! Reassembly of direct LED updates into per-channel frames.
+//!
+//! The host sends the colors of a channel in chunks of at most
+//! [`AURA_MAX_DIRECT_LED_COUNT`](crate::aura::constants::AURA_MAX_DIRECT_LED_COUNT)
+//! LEDs, and flags the last chunk of a frame with the apply bit.
+//! [`AuraFramebuffer`] writes the chunks into a back buffer, and only
+//! copies it to the front buffer when the apply bit arrives, so the
+//! front buffer never holds a half-written frame.
+
+use crate::aura::RGB8;
+use crate::pixel::BLACK;
+use crate::RogTerminalMessage;
+
+/// Signals that a whole frame was received for a channel, and its front
+/// buffer was updated.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FrameCommitted {
+    pub channel: u8,
+}
+
+/// Double-buffered frames for `CHANNELS` channels of up to `LEDS` LEDs.
+/// Up to 32 channels are supported.
+pub struct AuraFramebuffer<const CHANNELS: usize, const LEDS: usize> {
+    back: [[RGB8; LEDS]; CHANNELS],
+    front: [[RGB8; LEDS]; CHANNELS],
+    committed: u32,
+}
+
+impl<const CHANNELS: usize, const LEDS: usize> AuraFramebuffer<CHANNELS, LEDS> {
+    pub const fn new() -> Self {
+        const { assert!(CHANNELS <= 32, "Too many channels") };
+
+        Self {
+            back: [[BLACK; LEDS]; CHANNELS],
+            front: [[BLACK; LEDS]; CHANNELS],
+            committed: 0,
+        }
+    }
+
+    /// Applies a [`RogTerminalMessage::UpdateLeds`] message. Any other
+    /// message is ignored. The logo channel is stored at the index the
+    /// host addresses it with, right after the last strip channel.
+    pub fn apply(&mut self, message: &RogTerminalMessage) -> Option<FrameCommitted> {
+        match message {
+            RogTerminalMessage::UpdateLeds {
+                channel,
+                offset,
+                apply,
+                led_data,
+            } => self.update_leds(channel.index(), *offset as usize, *apply, led_data),
+            _ => None,
+        }
+    }
+
+    /// Writes `colors` into the back buffer of `channel`, starting at
+    /// `offset`. Colors beyond the end of the channel are discarded,
+    /// as well as updates for unknown channels. When `apply` is set,
+    /// the back buffer is copied to the front buffer.
+    ///
+    /// The back buffer keeps its contents after being applied, so hosts
+    /// that only send the LEDs that changed still get whole frames.
+    pub fn update_leds(
+        &mut self,
+        channel: u8,
+        offset: usize,
+        apply: bool,
+        colors: &[RGB8],
+    ) -> Option<FrameCommitted> {
+        let back = self.back.get_mut(channel as usize)?;
+
+        if offset < LEDS {
+            let len = colors.len().min(LEDS - offset);
+            back[offset..offset + len].copy_from_slice(&colors[..len]);
+        }
+
+        if !apply {
+            return None;
+        }
+
+        self.front[channel as usize] = *back;
+        self.committed |= 1 << channel;
+        Some(FrameCommitted { channel })
+    }
+
+    /// Returns the last committed frame of a channel.
+    pub fn front(&self, channel: u8) -> Option<&[RGB8; LEDS]> {
+        self.front.get(channel as usize)
+    }
+
+    /// Returns the frame being received for a channel.
+    pub fn back(&self, channel: u8) -> Option<&[RGB8; LEDS]> {
+        self.back.get(channel as usize)
+    }
+
+    /// Returns the frame being received for a channel, for applications
+    /// that render into it themselves.
+    pub fn back_mut(&mut self, channel: u8) -> Option<&mut [RGB8; LEDS]> {
+        self.back.get_mut(channel as usize)
+    }
+
+    /// Takes the next channel with a frame committed since the last
+    /// call, lowest channel first.
+    pub fn poll_committed(&mut self) -> Option<FrameCommitted> {
+        if self.committed == 0 {
+            return None;
+        }
+
+        let channel = self.committed.trailing_zeros() as u8;
+        self.committed &= !(1 << channel);
+        Some(FrameCommitted { channel })
+    }
+
+    /// Turns off every LED in both buffers.
+    pub fn clear(&mut self) {
+        self.back = [[BLACK; LEDS]; CHANNELS];
+        self.front = [[BLACK; LEDS]; CHANNELS];
+        self.committed = 0;
+    }
+}
+
+impl<const CHANNELS: usize, const LEDS: usize> Default for AuraFramebuffer<CHANNELS, LEDS> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,229 @@
+//! Host side of the Aura protocol, for driving a ROG Aura Terminal, or
+//! a device that impersonates it, from a host tool or a test.
+//!
+//! The [`AuraTerminalClient`] builds the requests and parses the
+//! responses, while an [`AuraTransport`] moves the reports to and from
+//! the device. The [`LoopbackTransport`] talks directly to an
+//! [`AsusRogTerminalHidClass`] living in the same process.
+
+use tinyvec::ArrayVec;
+use usb_device::bus::UsbBus;
+
+use crate::aura::constants::{
+    AURA_FIRMWARE_VERSION_LEN, AURA_INPUT_REPORT_SIZE, AURA_MAX_DIRECT_LED_COUNT,
+};
+use crate::aura::{
+    AuraConfigTable, AuraEffect, AuraEffectParams, AuraInputReport, AuraInputResponse,
+    AuraOutputReport, AuraOutputRequest, InvalidReportError, RGB8,
+};
+use crate::AsusRogTerminalHidClass;
+
+/// A channel able to carry Aura reports between the host and the
+/// device.
+pub trait AuraTransport {
+    type Error;
+
+    /// Sends a 65 bytes output report to the device.
+    fn send_output_report(&mut self, report: &AuraOutputReport) -> Result<(), Self::Error>;
+
+    /// Waits for the next 64 bytes input report sent by the device.
+    fn receive_input_report(&mut self, report: &mut AuraInputReport) -> Result<(), Self::Error>;
+}
+
+#[derive(Debug)]
+pub enum AuraClientError<E> {
+    /// The transport failed to send or receive a report.
+    Transport(E),
+
+    /// The device sent a report that couldn't be decoded.
+    InvalidResponse(InvalidReportError),
+
+    /// The device sent a valid response, but not the one expected for
+    /// the request.
+    UnexpectedResponse(AuraInputResponse),
+
+    /// The LEDs to set go past the last offset a report can address.
+    OffsetOutOfRange,
+}
+
+/// A client that talks the Aura protocol to a ROG Aura Terminal
+/// through an [`AuraTransport`].
+pub struct AuraTerminalClient<T: AuraTransport> {
+    transport: T,
+}
+
+impl<T: AuraTransport> AuraTerminalClient<T> {
+    pub fn new(transport: T) -> Self {
+        Self { transport }
+    }
+
+    pub fn transport(&self) -> &T {
+        &self.transport
+    }
+
+    pub fn transport_mut(&mut self) -> &mut T {
+        &mut self.transport
+    }
+
+    pub fn into_transport(self) -> T {
+        self.transport
+    }
+
+    /// Sends a raw request to the device, without waiting for any
+    /// response.
+    pub fn send(&mut self, request: &AuraOutputRequest) -> Result<(), AuraClientError<T::Error>> {
+        self.transport
+            .send_output_report(&request.encode())
+            .map_err(AuraClientError::Transport)
+    }
+
+    /// Waits for the next response sent by the device.
+    pub fn receive(&mut self) -> Result<AuraInputResponse, AuraClientError<T::Error>> {
+        let mut report: AuraInputReport = [0; AURA_INPUT_REPORT_SIZE];
+        self.transport
+            .receive_input_report(&mut report)
+            .map_err(AuraClientError::Transport)?;
+        AuraInputResponse::decode(&report).map_err(AuraClientError::InvalidResponse)
+    }
+
+    /// Requests the firmware version of the device.
+    pub fn firmware_version(
+        &mut self,
+    ) -> Result<[u8; AURA_FIRMWARE_VERSION_LEN as usize], AuraClientError<T::Error>> {
+        self.send(&AuraOutputRequest::FirmwareVersion)?;
+        match self.receive()? {
+            AuraInputResponse::FirmwareVersion(version) => Ok(version),
+            other => Err(AuraClientError::UnexpectedResponse(other)),
+        }
+    }
+
+    /// Requests the config table of the device.
+    pub fn config_table(&mut self) -> Result<AuraConfigTable, AuraClientError<T::Error>> {
+        self.send(&AuraOutputRequest::ConfigTable)?;
+        match self.receive()? {
+            AuraInputResponse::ConfigTable(table) => Ok(table),
+            other => Err(AuraClientError::UnexpectedResponse(other)),
+        }
+    }
+
+    /// Sets a preset effect in a channel.
+    pub fn set_effect(
+        &mut self,
+        channel: u8,
+        effect: AuraEffect,
+        params: AuraEffectParams,
+    ) -> Result<(), AuraClientError<T::Error>> {
+        self.send(&AuraOutputRequest::SetEffect {
+            channel,
+            effect,
+            params,
+        })
+    }
+
+    /// Sets the colors of the LEDs of a channel, starting at `offset`.
+    /// The colors are split in as many reports as needed, and only the
+    /// last one carries the apply flag, if requested. Nothing is sent if
+    /// the colors go past offset 255.
+    pub fn set_direct_leds(
+        &mut self,
+        channel: u8,
+        offset: u8,
+        colors: &[RGB8],
+        apply: bool,
+    ) -> Result<(), AuraClientError<T::Error>> {
+        if offset as usize + colors.len() > u8::MAX as usize + 1 {
+            return Err(AuraClientError::OffsetOutOfRange);
+        }
+
+        let mut chunks = colors.chunks(AURA_MAX_DIRECT_LED_COUNT as usize).peekable();
+        let mut chunk_offset = offset;
+
+        // An empty update is still sent, so the apply flag reaches the
+        // device.
+        if chunks.peek().is_none() {
+            return self.send(&AuraOutputRequest::SetDirectLeds {
+                channel,
+                offset,
+                apply,
+                led_data: ArrayVec::new(),
+            });
+        }
+
+        while let Some(chunk) = chunks.next() {
+            let mut led_data = ArrayVec::new();
+            led_data.extend_from_slice(chunk);
+
+            self.send(&AuraOutputRequest::SetDirectLeds {
+                channel,
+                offset: chunk_offset,
+                apply: apply && chunks.peek().is_none(),
+                led_data,
+            })?;
+            // Only the end of the last chunk can overflow.
+            match chunk_offset.checked_add(chunk.len() as u8) {
+                Some(next) => chunk_offset = next,
+                None => break,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The errors returned by a [`LoopbackTransport`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoopbackError {
+    /// The device has no response ready to be read.
+    NoResponse,
+
+    /// The class refused the report, as its message queue is full and
+    /// its overflow policy is
+    /// [`QueueOverflowPolicy::Refuse`](crate::QueueOverflowPolicy::Refuse).
+    Refused,
+}
+
+/// A transport that hands the reports directly to an
+/// [`AsusRogTerminalHidClass`], bypassing the USB bus. The messages
+/// produced by the class are still available through
+/// [`AsusRogTerminalHidClass::poll_next_message`].
+pub struct LoopbackTransport<'c, 'a, B: UsbBus, const Q: usize> {
+    class: &'c mut AsusRogTerminalHidClass<'a, B, Q>,
+}
+
+impl<'c, 'a, B: UsbBus, const Q: usize> LoopbackTransport<'c, 'a, B, Q> {
+    pub fn new(class: &'c mut AsusRogTerminalHidClass<'a, B, Q>) -> Self {
+        Self { class }
+    }
+
+    pub fn class(&self) -> &AsusRogTerminalHidClass<'a, B, Q> {
+        self.class
+    }
+
+    pub fn class_mut(&mut self) -> &mut AsusRogTerminalHidClass<'a, B, Q> {
+        self.class
+    }
+}
+
+impl<B: UsbBus, const Q: usize> AuraTransport for LoopbackTransport<'_, '_, B, Q> {
+    type Error = LoopbackError;
+
+    fn send_output_report(&mut self, report: &AuraOutputReport) -> Result<(), Self::Error> {
+        // Like the other transports, so the overflow policy applies.
+        let engine = self.class.engine_mut();
+        if !engine.admit_report() {
+            return Err(LoopbackError::Refused);
+        }
+
+        engine.handle_output_slice(report);
+        Ok(())
+    }
+
+    fn receive_input_report(&mut self, report: &mut AuraInputReport) -> Result<(), Self::Error> {
+        *report = self
+            .class
+            .engine_mut()
+            .pop_response()
+            .ok_or(LoopbackError::NoResponse)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,191 @@
+//! Runtime identity of the device: the firmware version reported to the
+//! host and the USB string descriptors.
+//!
+//! Hosts like OpenRGB tell identical controllers apart by their serial
+//! number, so every board should have its own. [`DeviceIdentity`] can
+//! derive one from the unique ID of the MCU:
+//!
+//! ```ignore
+//! let identity = DeviceIdentity::from_profile(&DeviceProfile::ROG_TERMINAL)
+//!     .with_firmware_version_str(env!("CARGO_PKG_VERSION"))
+//!     .with_serial_from_unique_id(&mcu_unique_id());
+//! let mut class = AsusRogTerminalHidClass::new_with_defaults(&alloc);
+//! class.set_identity(&identity);
+//! let mut device = identity
+//!     .apply(rog_terminal_usb_device_builder(&alloc))
+//!     .build();
+//! ```
+
+use usb_device::bus::UsbBus;
+use usb_device::device::{StringDescriptors, UsbDeviceBuilder};
+
+use crate::aura::constants::AURA_FIRMWARE_VERSION_LEN;
+use crate::profile::DeviceProfile;
+
+/// The maximum length of a serial number, in characters.
+pub const SERIAL_NUMBER_MAX_LEN: usize = 32;
+
+/// The firmware version reported to the host.
+pub type FirmwareVersion = [u8; AURA_FIRMWARE_VERSION_LEN as usize];
+
+const HEX_DIGITS: &[u8; 16] = b"0123456789ABCDEF";
+
+/// What the device tells the host about itself.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DeviceIdentity {
+    firmware_version: FirmwareVersion,
+    manufacturer: &'static str,
+    product: &'static str,
+    serial_number: [u8; SERIAL_NUMBER_MAX_LEN],
+    serial_number_len: usize,
+}
+
+impl DeviceIdentity {
+    /// Creates an identity with the given firmware version, and no
+    /// strings.
+    pub const fn new(firmware_version: FirmwareVersion) -> Self {
+        Self {
+            firmware_version,
+            manufacturer: "",
+            product: "",
+            serial_number: [0; SERIAL_NUMBER_MAX_LEN],
+            serial_number_len: 0,
+        }
+    }
+
+    /// Creates an identity with the firmware version and strings of a
+    /// profile, and no serial number.
+    pub const fn from_profile(profile: &DeviceProfile) -> Self {
+        Self::new(*profile.firmware_version).with_strings(profile.manufacturer, profile.product)
+    }
+
+    pub const fn with_firmware_version(mut self, firmware_version: FirmwareVersion) -> Self {
+        self.firmware_version = firmware_version;
+        self
+    }
+
+    /// Sets the firmware version from a string, like one generated from
+    /// the build info. Longer strings are truncated, and shorter ones
+    /// are padded with spaces, as hosts print the version as is, and
+    /// real versions like `AULA3-AR32-0207` always fill the field.
+    pub const fn with_firmware_version_str(mut self, firmware_version: &str) -> Self {
+        let bytes = firmware_version.as_bytes();
+        let mut i = 0;
+        while i < self.firmware_version.len() {
+            self.firmware_version[i] = if i < bytes.len() { bytes[i] } else { b' ' };
+            i += 1;
+        }
+        self
+    }
+
+    pub const fn with_strings(mut self, manufacturer: &'static str, product: &'static str) -> Self {
+        self.manufacturer = manufacturer;
+        self.product = product;
+        self
+    }
+
+    /// Sets the serial number. Non-ASCII characters are replaced with
+    /// a single `?`, and only the first [`SERIAL_NUMBER_MAX_LEN`]
+    /// characters are kept.
+    pub const fn with_serial_number(mut self, serial_number: &str) -> Self {
+        let bytes = serial_number.as_bytes();
+        let mut i = 0;
+        let mut len = 0;
+        while i < bytes.len() && len < SERIAL_NUMBER_MAX_LEN {
+            let byte = bytes[i];
+            i += 1;
+            self.serial_number[len] = if byte.is_ascii() {
+                byte
+            } else {
+                // Skip the continuation bytes of the character.
+                while i < bytes.len() && bytes[i] & 0xc0 == 0x80 {
+                    i += 1;
+                }
+                b'?'
+            };
+            len += 1;
+        }
+        self.serial_number_len = len;
+        self
+    }
+
+    /// Sets the serial number to the hexadecimal representation of a
+    /// unique ID, like the one most MCUs have burned in. IDs longer than
+    /// 16 bytes are folded over themselves to fit.
+    pub const fn with_serial_from_unique_id(mut self, unique_id: &[u8]) -> Self {
+        const ID_LEN: usize = SERIAL_NUMBER_MAX_LEN / 2;
+
+        let mut folded = [0u8; ID_LEN];
+        let mut i = 0;
+        while i < unique_id.len() {
+            folded[i % ID_LEN] ^= unique_id[i];
+            i += 1;
+        }
+
+        let len = if unique_id.len() < ID_LEN {
+            unique_id.len()
+        } else {
+            ID_LEN
+        };
+
+        let mut i = 0;
+        while i < len {
+            self.serial_number[i * 2] = HEX_DIGITS[(folded[i] >> 4) as usize];
+            self.serial_number[i * 2 + 1] = HEX_DIGITS[(folded[i] & 0x0f) as usize];
+            i += 1;
+        }
+        self.serial_number_len = len * 2;
+        self
+    }
+
+    pub const fn firmware_version(&self) -> &FirmwareVersion {
+        &self.firmware_version
+    }
+
+    pub const fn manufacturer(&self) -> &'static str {
+        self.manufacturer
+    }
+
+    pub const fn product(&self) -> &'static str {
+        self.product
+    }
+
+    /// Returns the serial number, if any.
+    pub fn serial_number(&self) -> Option<&str> {
+        if self.serial_number_len == 0 {
+            return None;
+        }
+
+        // Only ASCII characters are ever stored.
+        core::str::from_utf8(&self.serial_number[..self.serial_number_len]).ok()
+    }
+
+    /// Sets the string descriptors of a USB device builder. Empty
+    /// strings are left out. The identity must outlive the device, since
+    /// the descriptors borrow the strings from it.
+    pub fn apply<'a, B: UsbBus>(
+        &'a self,
+        builder: UsbDeviceBuilder<'a, B>,
+    ) -> UsbDeviceBuilder<'a, B> {
+        let mut strings = StringDescriptors::default();
+        if !self.manufacturer.is_empty() {
+            strings = strings.manufacturer(self.manufacturer);
+        }
+        if !self.product.is_empty() {
+            strings = strings.product(self.product);
+        }
+        if let Some(serial_number) = self.serial_number() {
+            strings = strings.serial_number(serial_number);
+        }
+
+        builder
+            .strings(&[strings])
+            .expect("A single language is always supported")
+    }
+}
+
+impl Default for DeviceIdentity {
+    fn default() -> Self {
+        Self::from_profile(&DeviceProfile::ROG_TERMINAL)
+    }
+}
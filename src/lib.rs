@@ -1,28 +1,17 @@
 #![no_std]
 
-pub mod aura;
-
-use aura::constants::{AURA_INPUT_REPORT_SIZE, AURA_OUTPUT_REPORT_SIZE};
-use aura::RGB8;
-use aura::{
-    constants::{AURA_FIRMWARE_VERSION_LEN, AURA_HID_REPORT_ID, AURA_MAX_DIRECT_LED_COUNT},
-    rgb_from_raw_slice, AuraEffect, AuraInputReport, AuraInputReportType, AuraOutputReport,
-    AuraOutputReportType,
-};
-use ringbuffer::{ConstGenericRingBuffer, RingBuffer};
-use tinyvec::ArrayVec;
-use usb_device::{
-    bus::{UsbBus, UsbBusAllocator},
-    class::UsbClass,
-    device::{UsbDeviceBuilder, UsbVidPid},
-};
-use usbd_hid::{hid_class::HIDClass, UsbError};
+#[cfg(any(feature = "capture", feature = "testing", feature = "uhid"))]
+extern crate std;
 
+// The diagnostics go to log, to defmt, or to both. Format strings must
+// be understood by both of them, so stick to {}, {:?} and {:0Nx}.
 macro_rules! dev_error {
     () => {};
     ($($arg:tt)*) => {
         #[cfg(feature = "log")]
         log::error!($($arg)*);
+        #[cfg(feature = "defmt")]
+        defmt::error!($($arg)*);
     }
 }
 
@@ -31,42 +20,68 @@ macro_rules! dev_info {
     ($($arg:tt)*) => {
         #[cfg(feature = "log")]
         log::info!($($arg)*);
+        #[cfg(feature = "defmt")]
+        defmt::info!($($arg)*);
     }
 }
 
-macro_rules! const_data {
-    (@internal $buf:ident $idx:ident ..)  => {
-        $idx = $buf.len()
-    };
-
-
-    (@internal $buf:ident $idx:ident $expr:expr)  => {
-        let slice = &($expr);
-        let mut cur = 0;
-        while cur < slice.len() {
-            $buf[$idx] = slice[cur];
-            $idx += 1;
-            cur += 1;
-        }
+// Hex-dumps a report at the trace level. log and defmt don't agree on
+// how to format a slice in hex, so this one has its own format strings.
+macro_rules! dev_trace_report {
+    ($what:expr, $report:expr) => {
+        #[cfg(feature = "log")]
+        log::trace!("{}: {:02x?}", $what, &$report[..]);
+        #[cfg(feature = "defmt")]
+        defmt::trace!("{=str}: {=[u8]:02x}", $what, &$report[..]);
     };
+}
 
-    ($len:literal, { $($gen:tt),* }) => {
-        {
-            let mut buf: [u8; $len] = [0; $len];
-            let mut idx: usize = 0;
-
-            $(
-            const_data!(@internal buf idx $gen);
-            )*
-
-            if idx < buf.len() {
-                panic!("Not enough bytes!!")
-            }
+pub mod aura;
+#[cfg(feature = "capture")]
+pub mod capture;
+pub mod color;
+pub mod effects;
+#[cfg(feature = "embassy")]
+pub mod embassy;
+pub mod engine;
+pub mod events;
+pub mod framebuffer;
+#[cfg(feature = "host")]
+pub mod host;
+pub mod identity;
+mod pixel;
+pub mod power;
+pub mod profile;
+pub mod session;
+#[cfg(feature = "smart-leds")]
+pub mod smart_leds;
+pub mod stats;
+#[cfg(feature = "testing")]
+pub mod testing;
+#[cfg(feature = "uhid")]
+pub mod uhid;
+
+use aura::constants::AURA_OUTPUT_REPORT_SIZE;
+use aura::RGB8;
+use aura::{
+    constants::{AURA_HID_REPORT_ID, AURA_MAX_DIRECT_LED_COUNT, AURA_MAX_EFFECT_COLOR_COUNT},
+    AuraChannel, AuraConfigTable, AuraEffect, AuraEffectParams, AuraInputReport, AuraOutputReport,
+};
+use engine::AuraProtocolEngine;
+use events::AuraEventHandler;
+use identity::{DeviceIdentity, FirmwareVersion};
+use profile::{AuraCommandSet, DeviceProfile};
+use session::AuraSessionRecorder;
+use stats::{AuraError, AuraStats};
+use tinyvec::ArrayVec;
+use usb_device::{
+    bus::{UsbBus, UsbBusAllocator},
+    class::UsbClass,
+    control::{Recipient, RequestType},
+    device::UsbDeviceBuilder,
+};
+use usbd_hid::{hid_class::HIDClass, UsbError};
 
-            buf
-        }
-    };
-}
 /// The HID descriptor used by an ROG Aura Terminal.
 pub const ROG_AURA_TERMINAL_HID_DESCRIPTOR: [u8; 36] = [
     0x06, 0x72, 0xff, // Usage Page (Vendor Usage Page 0xff72)
@@ -88,77 +103,347 @@ pub const ROG_AURA_TERMINAL_HID_DESCRIPTOR: [u8; 36] = [
     0xc0, // End Collection
 ];
 
+/// The HID class request used by the host to send output reports
+/// through the control endpoint.
+const HID_REQ_SET_REPORT: u8 = 0x09;
+
 pub const ROG_AURA_DEFAULT_FIRMWARE_VERSION: &[u8; 15] = b"AUTA0-S072-0101";
 
-// From my own tests, Armoury Crate doesn't give a fluff about any of
-// this data, except for the header (first 2 bytes) and, for whatever
-// reason, the byte at index 8. For now just sending all the data
-// possible to try to honor the original device behavior, just in case
-const CONFIG_TABLE_RESPONSE: [u8; 64] = const_data!(64, {
-    [
-        AURA_HID_REPORT_ID, AuraInputReportType::ConfigTableRequestOk as u8,
-        0x00, 0x00, 0x1f, 0xff,             // The fuck
-        0x04,                               // Number of channels
-        0x1f, 0x01, 0x01,                   // The fuck #2
-
-        0x00, 0x5a, 0x01, 0x64, 0x01, 0x01, // Channel 1 data: 0x5a refers to the number of leds in channel, although Armoury crate seems to be ignoring this value.
-        0x00, 0x5a, 0x01, 0x64, 0x01, 0x01, // Channel 2 data
-        0x00, 0x5a, 0x01, 0x64, 0x01, 0x01, // Channel 3 data
-        0x00, 0x5a, 0x01, 0x64, 0x01, 0x03, // Channel 4 data
-    ],
-    ..
-});
+/// The default number of messages that
+/// [`AsusRogTerminalHidClass`] can hold before the application polls
+/// them.
+pub const DEFAULT_MESSAGE_QUEUE_CAPACITY: usize = 8;
 
+#[derive(Clone, Debug, PartialEq)]
 pub enum RogTerminalMessage {
+    /// Sent when the host sets the colors of a channel. Updates for the
+    /// logo channel carry at most its single LED.
     UpdateLeds {
-        channel: u8,
+        channel: AuraChannel,
         offset: u8,
         apply: bool,
         led_data: ArrayVec<[RGB8; AURA_MAX_DIRECT_LED_COUNT as usize]>,
     },
 
     SetEffect {
-        channel: u8,
+        channel: AuraChannel,
+        effect: AuraEffect,
+        params: AuraEffectParams,
+    },
+
+    /// Sent when the host selects an effect through the generic Aura
+    /// USB
+    /// [`AuraOutputReportType::SetMode`](aura::AuraOutputReportType::SetMode)
+    /// request.
+    SetMode {
+        channel: AuraChannel,
         effect: AuraEffect,
+        shutdown: bool,
+    },
+
+    /// Sent when the host sets the colors of the current effect
+    /// through
+    /// [`AuraOutputReportType::SetEffectColors`](aura::AuraOutputReportType::SetEffectColors).
+    /// Only the entries of `colors` whose bit is set in `led_mask` are
+    /// meaningful.
+    SetEffectColors {
+        led_mask: u16,
+        shutdown: bool,
+        colors: [RGB8; AURA_MAX_EFFECT_COLOR_COUNT],
     },
+
+    /// Sent when the host asks the device to persist its current state.
+    Commit,
+
+    /// A report surfaced as is, according to the [`RawPassthrough`] of
+    /// the class. Responses can be sent with
+    /// [`AsusRogTerminalHidClass::queue_input_report`].
+    Raw(AuraOutputReport),
+}
+
+// Derived by hand, since ArrayVec doesn't implement Format.
+#[cfg(feature = "defmt")]
+impl defmt::Format for RogTerminalMessage {
+    fn format(&self, f: defmt::Formatter) {
+        match self {
+            RogTerminalMessage::UpdateLeds {
+                channel,
+                offset,
+                apply,
+                led_data,
+            } => defmt::write!(
+                f,
+                "UpdateLeds {{ channel: {}, offset: {}, apply: {}, led_data: {} }}",
+                channel,
+                offset,
+                apply,
+                led_data.as_slice()
+            ),
+            RogTerminalMessage::SetEffect {
+                channel,
+                effect,
+                params,
+            } => defmt::write!(
+                f,
+                "SetEffect {{ channel: {}, effect: {}, params: {} }}",
+                channel,
+                effect,
+                params
+            ),
+            RogTerminalMessage::SetMode {
+                channel,
+                effect,
+                shutdown,
+            } => defmt::write!(
+                f,
+                "SetMode {{ channel: {}, effect: {}, shutdown: {} }}",
+                channel,
+                effect,
+                shutdown
+            ),
+            RogTerminalMessage::SetEffectColors {
+                led_mask,
+                shutdown,
+                colors,
+            } => defmt::write!(
+                f,
+                "SetEffectColors {{ led_mask: {=u16:04x}, shutdown: {}, colors: {} }}",
+                led_mask,
+                shutdown,
+                colors
+            ),
+            RogTerminalMessage::Commit => defmt::write!(f, "Commit"),
+            RogTerminalMessage::Raw(report) => {
+                defmt::write!(f, "Raw({=[u8]:02x})", &report[..])
+            }
+        }
+    }
+}
+
+/// What to do when a new message is received while the message queue
+/// of [`AsusRogTerminalHidClass`] is full.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum QueueOverflowPolicy {
+    /// Discard the oldest message in the queue to make room for the
+    /// new one.
+    #[default]
+    DropOldest,
+
+    /// Discard the new message.
+    DropNewest,
+
+    /// Refuse the reports coming from the host until the application
+    /// polls some messages. Control transfers can't be NAKed once
+    /// their data stage has been received, so the SET_REPORT requests
+    /// are stalled instead. Hosts don't retry stalled requests, so the
+    /// refused reports are lost, but the sender learns about it, unlike
+    /// with the other policies. Refusals are counted in
+    /// [`AuraStats::refused_reports`].
+    Refuse,
+}
+
+/// Which reports are surfaced as is, for experimenting with commands
+/// this library doesn't know about.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum RawPassthrough {
+    /// Reports are only decoded.
+    #[default]
+    Off,
+
+    /// Reports with the Aura report ID but an unknown report type are
+    /// surfaced, instead of being discarded.
+    Unknown,
+
+    /// Every report with the Aura report ID is surfaced, in addition to
+    /// being decoded and handled as usual.
+    All,
 }
 
-enum RogTerminalReadyData {
-    FirmwareVersion,
-    ConfigTable,
+/// Receives the reports surfaced by [`RawPassthrough`], instead of them
+/// being queued as [`RogTerminalMessage::Raw`].
+pub trait RawReportHandler {
+    /// Handles a report. The returned report, if any, is sent to the
+    /// host as a response.
+    fn handle_raw_report(&mut self, report: &AuraOutputReport) -> Option<AuraInputReport>;
 }
 
-pub struct AsusRogTerminalHidClass<'a, B: UsbBus> {
+/// The Aura protocol over `usb-device`. A thin adapter that moves the
+/// reports between a [`HIDClass`] and an [`AuraProtocolEngine`], which
+/// does the rest.
+pub struct AsusRogTerminalHidClass<'a, B: UsbBus, const Q: usize = DEFAULT_MESSAGE_QUEUE_CAPACITY> {
     inner: HIDClass<'a, B>,
-    data_rdy: ConstGenericRingBuffer<RogTerminalReadyData, 4>,
-    next_message: Option<RogTerminalMessage>,
-    firmware_version: &'static [u8; AURA_FIRMWARE_VERSION_LEN as usize],
+    engine: AuraProtocolEngine<'a, Q>,
+    // The interface number of the HID class, which usbd-hid doesn't
+    // expose. It's learnt from the first report the class takes.
+    interface: Option<u8>,
 }
 
 impl<'a, B: UsbBus> AsusRogTerminalHidClass<'a, B> {
-    pub fn build_default_hid_class(alloc: &'a UsbBusAllocator<B>) -> HIDClass<B> {
-        HIDClass::new_ep_in(alloc, &ROG_AURA_TERMINAL_HID_DESCRIPTOR, 4)
+    pub fn build_default_hid_class(alloc: &'a UsbBusAllocator<B>) -> HIDClass<'a, B> {
+        DeviceProfile::ROG_TERMINAL.build_hid_class(alloc)
+    }
+
+    /// Creates the class with the HID descriptor, firmware version,
+    /// config table and supported commands of a profile. The device
+    /// should be built with [`DeviceProfile::usb_device_builder`] from
+    /// the same profile.
+    pub fn from_profile(alloc: &'a UsbBusAllocator<B>, profile: &DeviceProfile) -> Self {
+        Self::with_engine(
+            profile.build_hid_class(alloc),
+            AuraProtocolEngine::from_profile(profile),
+        )
     }
 
     pub fn new_with_defaults(alloc: &'a UsbBusAllocator<B>) -> Self {
         Self::new(
             Self::build_default_hid_class(alloc),
-            &ROG_AURA_DEFAULT_FIRMWARE_VERSION,
+            *ROG_AURA_DEFAULT_FIRMWARE_VERSION,
+            AuraConfigTable::default(),
         )
     }
 
     pub fn new(
         hid: HIDClass<'a, B>,
-        firmware_version: &'static [u8; AURA_FIRMWARE_VERSION_LEN as usize],
+        firmware_version: FirmwareVersion,
+        config_table: AuraConfigTable,
     ) -> Self {
+        Self::with_engine(hid, AuraProtocolEngine::new(firmware_version, config_table))
+    }
+}
+
+impl<'a, B: UsbBus, const Q: usize> AsusRogTerminalHidClass<'a, B, Q> {
+    /// Creates the class on top of an already configured engine.
+    pub fn with_engine(hid: HIDClass<'a, B>, engine: AuraProtocolEngine<'a, Q>) -> Self {
         Self {
             inner: hid,
-            data_rdy: ConstGenericRingBuffer::new(),
-            next_message: None,
-            firmware_version: &firmware_version,
+            engine,
+            interface: None,
+        }
+    }
+
+    /// Changes the capacity of the message queue. Any message already
+    /// queued is discarded.
+    pub fn with_queue_capacity<const N: usize>(self) -> AsusRogTerminalHidClass<'a, B, N> {
+        AsusRogTerminalHidClass {
+            inner: self.inner,
+            engine: self.engine.with_queue_capacity(),
+            interface: self.interface,
         }
     }
 
+    pub fn with_overflow_policy(mut self, policy: QueueOverflowPolicy) -> Self {
+        self.engine = self.engine.with_overflow_policy(policy);
+        self
+    }
+
+    pub fn overflow_policy(&self) -> QueueOverflowPolicy {
+        self.engine.overflow_policy()
+    }
+
+    pub fn set_overflow_policy(&mut self, policy: QueueOverflowPolicy) {
+        self.engine.set_overflow_policy(policy)
+    }
+
+    /// Returns the number of messages discarded because the message
+    /// queue was full.
+    pub fn dropped_messages(&self) -> u32 {
+        self.engine.dropped_messages()
+    }
+
+    /// Returns the protocol statistics since the class was created or
+    /// they were last reset.
+    pub fn stats(&self) -> &AuraStats {
+        self.engine.stats()
+    }
+
+    pub fn reset_stats(&mut self) {
+        self.engine.reset_stats()
+    }
+
+    /// Returns the number of messages waiting to be polled.
+    pub fn pending_messages(&self) -> usize {
+        self.engine.pending_messages()
+    }
+
+    /// Changes the output reports understood by the device. Any other
+    /// report is ignored.
+    pub fn with_supported_commands(mut self, supported_commands: AuraCommandSet) -> Self {
+        self.engine = self.engine.with_supported_commands(supported_commands);
+        self
+    }
+
+    pub fn supported_commands(&self) -> AuraCommandSet {
+        self.engine.supported_commands()
+    }
+
+    pub fn firmware_version(&self) -> &FirmwareVersion {
+        self.engine.firmware_version()
+    }
+
+    /// Changes the firmware version reported to the host from now on.
+    pub fn set_firmware_version(&mut self, firmware_version: FirmwareVersion) {
+        self.engine.set_firmware_version(firmware_version)
+    }
+
+    /// Reports the firmware version of an identity to the host. The
+    /// string descriptors have to be set on the device builder with
+    /// [`DeviceIdentity::apply`](identity::DeviceIdentity::apply).
+    pub fn set_identity(&mut self, identity: &DeviceIdentity) {
+        self.engine.set_identity(identity)
+    }
+
+    pub fn with_raw_passthrough(mut self, passthrough: RawPassthrough) -> Self {
+        self.engine = self.engine.with_raw_passthrough(passthrough);
+        self
+    }
+
+    pub fn raw_passthrough(&self) -> RawPassthrough {
+        self.engine.raw_passthrough()
+    }
+
+    pub fn set_raw_passthrough(&mut self, passthrough: RawPassthrough) {
+        self.engine.set_raw_passthrough(passthrough)
+    }
+
+    /// Sends the reports surfaced by the [`RawPassthrough`] to a handler
+    /// instead of queueing them as messages. Does nothing while the
+    /// passthrough is off.
+    pub fn set_raw_handler(&mut self, handler: Option<&'a mut dyn RawReportHandler>) {
+        self.engine.set_raw_handler(handler)
+    }
+
+    /// Invokes a handler for everything the host sends, as it is
+    /// received, instead of queueing messages. Messages already queued
+    /// are kept. The handler runs from the data stage of the SET_REPORT
+    /// request, before the request is acknowledged.
+    pub fn set_event_handler(&mut self, handler: Option<&'a mut dyn AuraEventHandler>) {
+        self.engine.set_event_handler(handler)
+    }
+
+    /// Hands every report exchanged with the host to a recorder, like a
+    /// [`session::SessionLogWriter`].
+    pub fn set_recorder(&mut self, recorder: Option<&'a mut dyn AuraSessionRecorder>) {
+        self.engine.set_recorder(recorder)
+    }
+
+    /// Queues a report to be sent to the host, like a response to a
+    /// [`RogTerminalMessage::Raw`] message.
+    pub fn queue_input_report(&mut self, report: AuraInputReport) {
+        self.engine.queue_input_report(report)
+    }
+
+    pub fn config_table(&self) -> &AuraConfigTable {
+        self.engine.config_table()
+    }
+
+    pub fn engine(&self) -> &AuraProtocolEngine<'a, Q> {
+        &self.engine
+    }
+
+    pub fn engine_mut(&mut self) -> &mut AuraProtocolEngine<'a, Q> {
+        &mut self.engine
+    }
+
     pub fn hid_class(&self) -> &HIDClass<'a, B> {
         &self.inner
     }
@@ -168,94 +453,39 @@ impl<'a, B: UsbBus> AsusRogTerminalHidClass<'a, B> {
     }
 
     fn push_ready_data(&mut self) -> Result<(), UsbError> {
-        while let Some(elem) = self.data_rdy.peek() {
-            match elem {
-                RogTerminalReadyData::FirmwareVersion => {
-                    let mut fw_report: AuraInputReport = [0u8; AURA_INPUT_REPORT_SIZE];
-                    fw_report[0] = AURA_HID_REPORT_ID;
-                    fw_report[1] = AuraInputReportType::FirmwareVersionRequestOk as u8;
-                    fw_report[2..17].copy_from_slice(self.firmware_version);
-                    self.inner.push_raw_input(&fw_report)?;
-                }
-                RogTerminalReadyData::ConfigTable => {
-                    self.inner.push_raw_input(&CONFIG_TABLE_RESPONSE)?;
-                }
-            }
-            self.data_rdy.dequeue();
+        while let Some(report) = self.engine.peek_response() {
+            self.inner.push_raw_input(&report)?;
+            self.engine.pop_response();
         }
 
         Ok(())
     }
 
-    fn handle_report(&mut self, report: &AuraOutputReport) {
-        let report_id = report[0];
-        let report_type = report[1];
-
-        if report_id != AURA_HID_REPORT_ID {
-            dev_error!("Unrecognized report ID: {}", report_id);
-            return
+    fn push_ready_data_or_record(&mut self) {
+        match self.push_ready_data() {
+            Ok(()) | Err(UsbError::WouldBlock) => {}
+            Err(e) => self.engine.record_error(AuraError::InputPushFailed(e)),
         }
+    }
 
-        let Ok(report_type) = AuraOutputReportType::try_from(report_type) else {
-            dev_error!("Received unrecognized request type: {}", report_type);
-            return;
-        };
-
-        match report_type {
-            AuraOutputReportType::FirmwareVersionRequest => {
-                dev_info!("Host requested firmware version");
-                self.data_rdy.push(RogTerminalReadyData::FirmwareVersion)
-            }
-            AuraOutputReportType::ConfigTableRequest => {
-                dev_info!("Host requested device configuration table");
-                self.data_rdy.push(RogTerminalReadyData::ConfigTable)
-            }
-            AuraOutputReportType::SetEffect => {
-                let channel = report[2];
-                let effect_code = report[4];
-                let Ok(effect) = AuraEffect::try_from(effect_code) else {
-                    dev_error!("Unknown effect code received: {:02x}", effect_code);
-                    return;
-                };
-
-                dev_info!(
-                    "Host requested set effect for ch {} to {:02x}",
-                    channel,
-                    effect_code
-                );
-                self.next_message = Some(RogTerminalMessage::SetEffect { channel, effect })
-            }
-            AuraOutputReportType::SetDirectLeds => {
-                let apply = (report[2] & 0x80) > 0;
-                let channel = report[2] & 0x7f;
-
-                let offset = report[3];
-                let mut num_leds = report[4];
-                if num_leds > AURA_MAX_DIRECT_LED_COUNT {
-                    dev_error!("Host sent a led count greater than maximum ({})", num_leds);
-                    num_leds = AURA_MAX_DIRECT_LED_COUNT;
-                }
-
-                let mut led_data = [RGB8 { r: 0, g: 0, b: 0 }; AURA_MAX_DIRECT_LED_COUNT as usize];
-                led_data[0..num_leds as usize]
-                    .copy_from_slice(rgb_from_raw_slice(&report[5..5 + num_leds as usize * 3]));
-
-                self.next_message = Some(RogTerminalMessage::UpdateLeds {
-                    channel,
-                    apply,
-                    offset,
-                    led_data: ArrayVec::from_array_len(led_data, num_leds as usize),
-                });
-            }
+    /// Hands the report buffered by the HID class, if any, to the
+    /// engine. Returns false if there was none.
+    fn pull_report(&mut self) -> bool {
+        let mut reportbuf: AuraOutputReport = [0; AURA_OUTPUT_REPORT_SIZE];
+        match self.inner.pull_raw_report(&mut reportbuf) {
+            Ok(_) => self.engine.handle_output_report(&reportbuf),
+            Err(UsbError::WouldBlock) => return false,
+            Err(e) => self.engine.record_error(AuraError::ReportReadFailed(e)),
         }
+        true
     }
 
     pub fn poll_next_message(&mut self) -> Option<RogTerminalMessage> {
-        self.next_message.take()
+        self.engine.poll_next_message()
     }
 }
 
-impl<'a, B: UsbBus> UsbClass<B> for AsusRogTerminalHidClass<'a, B> {
+impl<B: UsbBus, const Q: usize> UsbClass<B> for AsusRogTerminalHidClass<'_, B, Q> {
     #[inline]
     fn get_configuration_descriptors(
         &self,
@@ -288,7 +518,31 @@ impl<'a, B: UsbBus> UsbClass<B> for AsusRogTerminalHidClass<'a, B> {
 
     #[inline]
     fn control_out(&mut self, xfer: usb_device::class::ControlOut<B>) {
-        self.inner.control_out(xfer)
+        let req = *xfer.request();
+        let is_set_report = req.request_type == RequestType::Class
+            && req.recipient == Recipient::Interface
+            && req.request == HID_REQ_SET_REPORT;
+        if !is_set_report || self.interface.is_some_and(|i| req.index != i as u16) {
+            self.inner.control_out(xfer);
+            return;
+        }
+
+        // Until the interface is known, the class didn't take any report,
+        // so there is nothing to refuse yet.
+        if self.interface.is_some()
+            && xfer.data().first() == Some(&AURA_HID_REPORT_ID)
+            && !self.engine.admit_report()
+        {
+            xfer.reject().ok();
+            return;
+        }
+
+        self.inner.control_out(xfer);
+        // The HID class only buffers one report, so hand it to the
+        // engine before the host sends the next one.
+        if self.pull_report() {
+            self.interface = Some(req.index as u8);
+        }
     }
 
     #[inline]
@@ -327,25 +581,14 @@ impl<'a, B: UsbBus> UsbClass<B> for AsusRogTerminalHidClass<'a, B> {
 
     fn poll(&mut self) {
         self.inner.poll();
-        let mut reportbuf: AuraOutputReport = [0; AURA_OUTPUT_REPORT_SIZE];
-        match self.inner.pull_raw_report(&mut reportbuf) {
-            Ok(_) => {
-                self.handle_report(&reportbuf);
-            }
-            Err(e) =>
-            {
-                #[cfg(feature = "log")]
-                if !matches!(e, UsbError::WouldBlock) {
-                    dev_error!("Fail to pull report: {:?}", e);
-                }
-            }
-        }
-        let _ = self.push_ready_data();
+        self.push_ready_data_or_record();
     }
 }
 
+/// Creates a USB device builder that identifies the device as a ROG Aura
+/// Terminal. See [`DeviceProfile`] for other devices.
 pub fn rog_terminal_usb_device_builder<B: UsbBus>(
     alloc: &UsbBusAllocator<B>,
-) -> UsbDeviceBuilder<B> {
-    UsbDeviceBuilder::new(alloc, UsbVidPid(0x0b05, 0x1889))
+) -> UsbDeviceBuilder<'_, B> {
+    DeviceProfile::ROG_TERMINAL.usb_device_builder(alloc)
 }
@@ -0,0 +1,19 @@
+//! Pixel helpers shared by the rendering and color modules.
+
+use crate::aura::RGB8;
+
+pub(crate) const BLACK: RGB8 = RGB8 { r: 0, g: 0, b: 0 };
+
+/// Scales a single channel by `level`, where 255 keeps it unchanged.
+pub(crate) const fn scale8(value: u8, level: u8) -> u8 {
+    ((value as u16 * (level as u16 + 1)) >> 8) as u8
+}
+
+/// Scales every channel of a color by `level`, like [`scale8`].
+pub(crate) const fn scale(color: RGB8, level: u8) -> RGB8 {
+    RGB8 {
+        r: scale8(color.r, level),
+        g: scale8(color.g, level),
+        b: scale8(color.b, level),
+    }
+}
@@ -0,0 +1,269 @@
+//! Limiting of the current drawn by the LEDs.
+//!
+//! A full-white frame on four channels of 90 LEDs draws several amps,
+//! way more than what a USB port can supply. [`PowerLimiter`] estimates
+//! the current a frame would draw using a [`LedPowerModel`], and dims
+//! it just enough so it stays under the budget of its channel and the
+//! budget of the whole device.
+//!
+//! The limiter should be the last stage before the LEDs, after the
+//! [`crate::color::ColorPipeline`], since the current depends on the
+//! values actually sent to them.
+
+use crate::aura::RGB8;
+use crate::pixel;
+
+/// The current drawn by a single LED.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LedPowerModel {
+    /// The current of the red component at full value, in µA.
+    pub red_ua: u32,
+
+    /// The current of the green component at full value, in µA.
+    pub green_ua: u32,
+
+    /// The current of the blue component at full value, in µA.
+    pub blue_ua: u32,
+
+    /// The current of the LED when it is off, in µA.
+    pub idle_ua: u32,
+}
+
+impl LedPowerModel {
+    /// From the datasheet of the WS2812B, which is a bit pessimistic.
+    pub const WS2812B: Self = Self::new(20_000, 20_000, 20_000, 1_000);
+
+    pub const fn new(red_ua: u32, green_ua: u32, blue_ua: u32, idle_ua: u32) -> Self {
+        Self {
+            red_ua,
+            green_ua,
+            blue_ua,
+            idle_ua,
+        }
+    }
+
+    /// Returns the current drawn by `leds`, in µA, split in the part
+    /// that doesn't depend on the colors and the part that does.
+    fn current_ua(&self, leds: &[RGB8]) -> (u64, u64) {
+        let (mut r, mut g, mut b) = (0u64, 0u64, 0u64);
+        for led in leds {
+            r += led.r as u64;
+            g += led.g as u64;
+            b += led.b as u64;
+        }
+
+        let idle = leds.len() as u64 * self.idle_ua as u64;
+        let colors =
+            (r * self.red_ua as u64 + g * self.green_ua as u64 + b * self.blue_ua as u64) / 255;
+        (idle, colors)
+    }
+}
+
+impl Default for LedPowerModel {
+    fn default() -> Self {
+        Self::WS2812B
+    }
+}
+
+/// What the limiter did to a frame.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PowerReport {
+    /// The current the frame would have drawn, in mA.
+    pub requested_ma: u32,
+
+    /// The current the frame draws after being limited, in mA.
+    pub drawn_ma: u32,
+
+    /// The scale applied to the colors, where 255 means that the frame
+    /// was left untouched.
+    pub scale: u8,
+}
+
+impl PowerReport {
+    /// Whether the frame had to be dimmed.
+    pub fn is_limited(&self) -> bool {
+        self.scale != 255
+    }
+}
+
+/// Limits the current drawn by `CHANNELS` channels.
+///
+/// The limiter remembers the current drawn by the last frame of every
+/// channel, so channels can be limited one at a time, as their frames
+/// are committed, and still honor the total budget. A channel only gets
+/// what the others left of the total budget, so when every channel is
+/// bright, the ones limited first win. Setting channel budgets that add
+/// up to the total budget splits it evenly instead.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PowerLimiter<const CHANNELS: usize> {
+    model: LedPowerModel,
+    channel_budget_ma: [Option<u32>; CHANNELS],
+    total_budget_ma: Option<u32>,
+    drawn_ua: [u64; CHANNELS],
+    reports: [PowerReport; CHANNELS],
+}
+
+impl<const CHANNELS: usize> PowerLimiter<CHANNELS> {
+    /// Creates a limiter with no budgets, which leaves every frame
+    /// untouched.
+    pub const fn new(model: LedPowerModel) -> Self {
+        Self {
+            model,
+            channel_budget_ma: [None; CHANNELS],
+            total_budget_ma: None,
+            drawn_ua: [0; CHANNELS],
+            reports: [PowerReport {
+                requested_ma: 0,
+                drawn_ma: 0,
+                scale: 255,
+            }; CHANNELS],
+        }
+    }
+
+    /// Sets the same budget for every channel.
+    pub const fn with_channel_budget(mut self, budget_ma: u32) -> Self {
+        self.channel_budget_ma = [Some(budget_ma); CHANNELS];
+        self
+    }
+
+    /// Sets the budget of the whole device.
+    pub const fn with_total_budget(mut self, budget_ma: u32) -> Self {
+        self.total_budget_ma = Some(budget_ma);
+        self
+    }
+
+    pub fn model(&self) -> &LedPowerModel {
+        &self.model
+    }
+
+    pub fn set_model(&mut self, model: LedPowerModel) {
+        self.model = model;
+    }
+
+    pub fn channel_budget(&self, channel: u8) -> Option<u32> {
+        self.channel_budget_ma
+            .get(channel as usize)
+            .copied()
+            .flatten()
+    }
+
+    /// Sets the budget of a channel, or removes it with `None`.
+    pub fn set_channel_budget(&mut self, channel: u8, budget_ma: Option<u32>) {
+        if let Some(budget) = self.channel_budget_ma.get_mut(channel as usize) {
+            *budget = budget_ma;
+        }
+    }
+
+    pub fn total_budget(&self) -> Option<u32> {
+        self.total_budget_ma
+    }
+
+    /// Sets the budget of the whole device, or removes it with `None`.
+    pub fn set_total_budget(&mut self, budget_ma: Option<u32>) {
+        self.total_budget_ma = budget_ma;
+    }
+
+    /// Returns what the limiter did to the last frame of a channel.
+    pub fn last_report(&self, channel: u8) -> Option<&PowerReport> {
+        self.reports.get(channel as usize)
+    }
+
+    /// Returns the current drawn by the last frame of every channel, in
+    /// mA.
+    pub fn drawn_ma(&self) -> u32 {
+        to_ma(self.drawn_ua.iter().sum())
+    }
+
+    /// Returns the current available to a channel, in µA, given its own
+    /// budget and what's left of the total budget.
+    fn available_ua(&self, channel: usize) -> Option<u64> {
+        let channel_budget = self.channel_budget_ma[channel].map(|ma| ma as u64 * 1000);
+        let total_budget = self.total_budget_ma.map(|ma| {
+            let others: u64 = self
+                .drawn_ua
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| *i != channel)
+                .map(|(_, ua)| ua)
+                .sum();
+            (ma as u64 * 1000).saturating_sub(others)
+        });
+
+        match (channel_budget, total_budget) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        }
+    }
+
+    /// Dims the frame of a channel in place so it stays under budget,
+    /// like the front buffer of a
+    /// [`crate::framebuffer::AuraFramebuffer`] or the output of a
+    /// [`crate::effects::AuraEffectRenderer`]. Frames of unknown
+    /// channels are left untouched.
+    pub fn limit(&mut self, channel: u8, leds: &mut [RGB8]) -> PowerReport {
+        let index = channel as usize;
+        if index >= CHANNELS {
+            return PowerReport {
+                scale: 255,
+                ..Default::default()
+            };
+        }
+
+        let (idle, colors) = self.model.current_ua(leds);
+        let requested = idle + colors;
+
+        let scale = match self.available_ua(index) {
+            Some(available) if requested > available && colors > 0 => {
+                // Only the color-dependent current can be scaled down,
+                // and scale8 multiplies by (scale + 1) / 256.
+                let factor = available.saturating_sub(idle) * 256 / colors;
+                factor.saturating_sub(1).min(255) as u8
+            }
+            _ => 255,
+        };
+
+        let drawn = if scale == 255 {
+            requested
+        } else {
+            for led in leds.iter_mut() {
+                *led = pixel::scale(*led, scale);
+            }
+            let (idle, colors) = self.model.current_ua(leds);
+            idle + colors
+        };
+
+        let report = PowerReport {
+            requested_ma: to_ma(requested),
+            drawn_ma: to_ma(drawn),
+            scale,
+        };
+        self.drawn_ua[index] = drawn;
+        self.reports[index] = report;
+        report
+    }
+
+    /// Like [`PowerLimiter::limit`], but takes the colors from `src` and
+    /// writes the limited ones into `dst`. Only as many colors as fit in
+    /// both buffers are taken into account.
+    pub fn limit_into(&mut self, channel: u8, src: &[RGB8], dst: &mut [RGB8]) -> PowerReport {
+        let len = src.len().min(dst.len());
+        dst[..len].copy_from_slice(&src[..len]);
+        self.limit(channel, &mut dst[..len])
+    }
+
+    /// Forgets the current drawn by every channel, like after turning
+    /// the LEDs off.
+    pub fn reset(&mut self) {
+        self.drawn_ua = [0; CHANNELS];
+    }
+}
+
+impl<const CHANNELS: usize> Default for PowerLimiter<CHANNELS> {
+    fn default() -> Self {
+        Self::new(LedPowerModel::default())
+    }
+}
+
+fn to_ma(ua: u64) -> u32 {
+    (ua / 1000).min(u32::MAX as u64) as u32
+}
@@ -0,0 +1,221 @@
+//! Identities of the Aura USB devices this library can impersonate.
+//!
+//! Every host application supports a different set of Aura devices, so
+//! the device that works best depends on the host. A [`DeviceProfile`]
+//! bundles everything that identifies a device: the USB IDs and
+//! strings, the HID descriptor, the firmware version and config table
+//! reported to the host, and the commands the device understands.
+//!
+//! Only [`DeviceProfile::ROG_TERMINAL`] comes from the original device.
+//! The other presets are approximations: their IDs, strings and
+//! firmware versions are the ones host applications look for, but they
+//! reuse the HID descriptor of the Terminal and the header of its
+//! config table, since their own haven't been captured. That's enough
+//! for OpenRGB, but a host checking those may tell them apart from the
+//! real thing.
+//!
+//! ```ignore
+//! let profile = DeviceProfile::AURA_ADDRESSABLE_HEADERS;
+//! let mut class = AsusRogTerminalHidClass::from_profile(&alloc, &profile);
+//! let mut device = profile.usb_device_builder(&alloc).build();
+//! ```
+
+use usb_device::bus::{UsbBus, UsbBusAllocator};
+use usb_device::device::{StringDescriptors, UsbDeviceBuilder, UsbVidPid};
+use usbd_hid::hid_class::HIDClass;
+
+use crate::aura::constants::AURA_FIRMWARE_VERSION_LEN;
+use crate::aura::{AuraConfigTable, AuraOutputReportType};
+use crate::{ROG_AURA_DEFAULT_FIRMWARE_VERSION, ROG_AURA_TERMINAL_HID_DESCRIPTOR};
+
+/// The vendor ID of every ASUS device.
+pub const ASUS_VENDOR_ID: u16 = 0x0b05;
+
+/// The polling interval of the interrupt IN endpoint, in milliseconds.
+pub(crate) const HID_POLL_INTERVAL_MS: u8 = 4;
+
+/// The set of output reports a device understands. Reports outside the
+/// set are ignored, like a device that doesn't know them would do.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct AuraCommandSet {
+    bits: u8,
+}
+
+impl AuraCommandSet {
+    /// No commands at all.
+    pub const NONE: Self = Self { bits: 0 };
+
+    /// Every command known by this library.
+    pub const ALL: Self = Self::NONE
+        .with(AuraOutputReportType::FirmwareVersionRequest)
+        .with(AuraOutputReportType::ConfigTableRequest)
+        .with(AuraOutputReportType::SetEffect)
+        .with(AuraOutputReportType::SetDirectLeds)
+        .with(AuraOutputReportType::SetMode)
+        .with(AuraOutputReportType::SetEffectColors)
+        .with(AuraOutputReportType::Commit);
+
+    /// The commands of the generic Aura USB controllers, that don't
+    /// know about the [`AuraOutputReportType::SetEffect`] request of
+    /// the ROG Aura Terminal.
+    pub const AURA_USB: Self = Self::ALL.without(AuraOutputReportType::SetEffect);
+
+    const fn bit(report_type: AuraOutputReportType) -> u8 {
+        match report_type {
+            AuraOutputReportType::FirmwareVersionRequest => 1 << 0,
+            AuraOutputReportType::ConfigTableRequest => 1 << 1,
+            AuraOutputReportType::SetEffect => 1 << 2,
+            AuraOutputReportType::SetDirectLeds => 1 << 3,
+            AuraOutputReportType::SetMode => 1 << 4,
+            AuraOutputReportType::SetEffectColors => 1 << 5,
+            AuraOutputReportType::Commit => 1 << 6,
+        }
+    }
+
+    pub const fn with(mut self, report_type: AuraOutputReportType) -> Self {
+        self.bits |= Self::bit(report_type);
+        self
+    }
+
+    pub const fn without(mut self, report_type: AuraOutputReportType) -> Self {
+        self.bits &= !Self::bit(report_type);
+        self
+    }
+
+    pub const fn contains(&self, report_type: AuraOutputReportType) -> bool {
+        self.bits & Self::bit(report_type) != 0
+    }
+}
+
+impl Default for AuraCommandSet {
+    fn default() -> Self {
+        Self::ALL
+    }
+}
+
+/// Everything that identifies an Aura USB device to the host.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DeviceProfile {
+    pub vendor_id: u16,
+    pub product_id: u16,
+    pub manufacturer: &'static str,
+    pub product: &'static str,
+    pub hid_descriptor: &'static [u8],
+    pub firmware_version: &'static [u8; AURA_FIRMWARE_VERSION_LEN as usize],
+    pub config_table: AuraConfigTable,
+    pub supported_commands: AuraCommandSet,
+}
+
+impl DeviceProfile {
+    /// The ROG Aura Terminal: four channels of 90 LEDs, plus the logo.
+    pub const ROG_TERMINAL: Self = Self {
+        vendor_id: ASUS_VENDOR_ID,
+        product_id: 0x1889,
+        manufacturer: "ASUSTeK Computer Inc.",
+        product: "ROG AURA TERMINAL",
+        hid_descriptor: &ROG_AURA_TERMINAL_HID_DESCRIPTOR,
+        firmware_version: ROG_AURA_DEFAULT_FIRMWARE_VERSION,
+        config_table: AuraConfigTable::ROG_TERMINAL,
+        supported_commands: AuraCommandSet::ALL,
+    };
+
+    /// The USB Aura LED controller found in older motherboards, with a
+    /// single addressable header. An approximation, see the
+    /// [module documentation](self).
+    pub const AURA_LED_CONTROLLER: Self = Self {
+        vendor_id: ASUS_VENDOR_ID,
+        product_id: 0x1867,
+        manufacturer: "AsusTek Computer Inc.",
+        product: "AURA LED Controller",
+        hid_descriptor: &ROG_AURA_TERMINAL_HID_DESCRIPTOR,
+        firmware_version: b"AULA1-S072-0207",
+        config_table: AuraConfigTable::new().with_channel(0x78),
+        supported_commands: AuraCommandSet::AURA_USB,
+    };
+
+    /// The USB Aura controller of newer motherboards, with three
+    /// addressable headers. An approximation, see the
+    /// [module documentation](self).
+    pub const AURA_ADDRESSABLE_HEADERS: Self = Self {
+        vendor_id: ASUS_VENDOR_ID,
+        product_id: 0x18f3,
+        manufacturer: "AsusTek Computer Inc.",
+        product: "AURA LED Controller",
+        hid_descriptor: &ROG_AURA_TERMINAL_HID_DESCRIPTOR,
+        firmware_version: b"AULA3-AR32-0207",
+        config_table: AuraConfigTable::new()
+            .with_channel(0x78)
+            .with_channel(0x78)
+            .with_channel(0x78),
+        supported_commands: AuraCommandSet::AURA_USB,
+    };
+
+    pub const fn with_ids(mut self, vendor_id: u16, product_id: u16) -> Self {
+        self.vendor_id = vendor_id;
+        self.product_id = product_id;
+        self
+    }
+
+    pub const fn with_strings(mut self, manufacturer: &'static str, product: &'static str) -> Self {
+        self.manufacturer = manufacturer;
+        self.product = product;
+        self
+    }
+
+    pub const fn with_firmware_version(
+        mut self,
+        firmware_version: &'static [u8; AURA_FIRMWARE_VERSION_LEN as usize],
+    ) -> Self {
+        self.firmware_version = firmware_version;
+        self
+    }
+
+    pub const fn with_config_table(mut self, config_table: AuraConfigTable) -> Self {
+        self.config_table = config_table;
+        self
+    }
+
+    pub const fn with_supported_commands(mut self, supported_commands: AuraCommandSet) -> Self {
+        self.supported_commands = supported_commands;
+        self
+    }
+
+    /// Creates the HID class with the descriptor of the profile.
+    pub fn build_hid_class<'a, B: UsbBus>(&self, alloc: &'a UsbBusAllocator<B>) -> HIDClass<'a, B> {
+        HIDClass::new_ep_in(alloc, self.hid_descriptor, HID_POLL_INTERVAL_MS)
+    }
+
+    /// Creates a USB device builder with the IDs and strings of the
+    /// profile.
+    pub fn usb_device_builder<'a, B: UsbBus>(
+        &self,
+        alloc: &'a UsbBusAllocator<B>,
+    ) -> UsbDeviceBuilder<'a, B> {
+        UsbDeviceBuilder::new(alloc, UsbVidPid(self.vendor_id, self.product_id))
+            .strings(&[StringDescriptors::default()
+                .manufacturer(self.manufacturer)
+                .product(self.product)])
+            .expect("A single language is always supported")
+    }
+
+    /// Returns the configuration of an embassy-usb device that
+    /// impersonates the one of the profile.
+    #[cfg(feature = "embassy")]
+    pub fn embassy_config(&self) -> embassy_usb::Config<'static> {
+        let mut config = embassy_usb::Config::new(self.vendor_id, self.product_id);
+        config.manufacturer = Some(self.manufacturer);
+        config.product = Some(self.product);
+        // A plain HID device, like the real ones, not a composite one.
+        config.device_class = 0;
+        config.device_sub_class = 0;
+        config.device_protocol = 0;
+        config.composite_with_iads = false;
+        config
+    }
+}
+
+impl Default for DeviceProfile {
+    fn default() -> Self {
+        Self::ROG_TERMINAL
+    }
+}
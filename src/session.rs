@@ -0,0 +1,466 @@
+//! Recording and replaying of the reports exchanged with the host.
+//!
+//! A session log is a compact binary format: a header made of
+//! [`SESSION_LOG_MAGIC`] and [`SESSION_LOG_VERSION`], followed by one
+//! record per report:
+//!
+//! | Byte  | Content                                                   |
+//! |-------|-----------------------------------------------------------|
+//! | 0     | Direction (see [`SessionDirection`])                      |
+//! | 1..5  | Microseconds since the previous record (u32 LE)           |
+//! | 5     | Length of the report, without its trailing zeros          |
+//! | 6..   | The report, including its report ID for output reports    |
+//!
+//! Reports are mostly zero padding, so the trailing zeros are trimmed
+//! and added back when the log is read.
+//!
+//! The [`SessionLogWriter`] records a session when set as the
+//! [`AuraSessionRecorder`] of the class, and the [`SessionReplayer`]
+//! sends the recorded output reports again, checking that the same
+//! responses come back.
+//!
+//! ```ignore
+//! let mut buf = [0; 4096];
+//! let mut recorder = SessionLogWriter::new(&mut buf, || now_us()).unwrap();
+//! class.set_recorder(Some(&mut recorder));
+//! ...
+//! let mut engine = AuraProtocolEngine::default();
+//! SessionReplayer::new(log)?.replay(&mut engine, &expected_messages)?;
+//! ```
+
+use core::convert::Infallible;
+
+use crate::aura::constants::{AURA_INPUT_REPORT_SIZE, AURA_OUTPUT_REPORT_SIZE};
+use crate::aura::{AuraInputReport, AuraOutputReport};
+use crate::engine::AuraProtocolEngine;
+use crate::RogTerminalMessage;
+
+/// The first bytes of every session log.
+pub const SESSION_LOG_MAGIC: [u8; 4] = *b"AURS";
+
+/// The version of the session log format written by this crate.
+pub const SESSION_LOG_VERSION: u8 = 1;
+
+pub const SESSION_LOG_HEADER_SIZE: usize = SESSION_LOG_MAGIC.len() + 1;
+
+const RECORD_HEADER_SIZE: usize = 6;
+
+/// The size of the largest record, the one of an output report without
+/// trailing zeros.
+pub const SESSION_RECORD_MAX_SIZE: usize = RECORD_HEADER_SIZE + AURA_OUTPUT_REPORT_SIZE;
+
+/// Who sent a report.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SessionDirection {
+    /// An output report, sent by the host.
+    Output = 0,
+
+    /// An input report, sent by the device.
+    Input = 1,
+}
+
+impl SessionDirection {
+    /// The size of the reports sent in this direction.
+    pub const fn report_size(self) -> usize {
+        match self {
+            SessionDirection::Output => AURA_OUTPUT_REPORT_SIZE,
+            SessionDirection::Input => AURA_INPUT_REPORT_SIZE,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SessionLogError {
+    /// The log doesn't start with [`SESSION_LOG_MAGIC`].
+    BadMagic,
+
+    /// The log was written with a version of the format this crate
+    /// doesn't know.
+    UnsupportedVersion(u8),
+
+    /// The log ends in the middle of a record.
+    Truncated,
+
+    /// A record has an unknown direction byte.
+    InvalidDirection(u8),
+
+    /// A record carries a report longer than the reports of its
+    /// direction.
+    InvalidLength(u8),
+
+    /// There is no room left in the buffer for the record.
+    BufferFull,
+}
+
+/// A report exchanged with the host, and when it was.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SessionRecord {
+    /// Microseconds since an arbitrary point, usually the start of the
+    /// session.
+    pub timestamp_us: u64,
+    pub direction: SessionDirection,
+    report: [u8; AURA_OUTPUT_REPORT_SIZE],
+    truncated_bytes: u16,
+}
+
+impl SessionRecord {
+    /// Creates a record of a report. Reports shorter than the ones of
+    /// their direction are padded with zeros, and longer ones are
+    /// truncated, which [`SessionRecord::truncated_bytes`] tells.
+    pub fn new(timestamp_us: u64, direction: SessionDirection, report: &[u8]) -> Self {
+        let len = report.len().min(direction.report_size());
+        let mut data = [0; AURA_OUTPUT_REPORT_SIZE];
+        data[..len].copy_from_slice(&report[..len]);
+
+        Self {
+            timestamp_us,
+            direction,
+            report: data,
+            truncated_bytes: (report.len() - len).min(u16::MAX as usize) as u16,
+        }
+    }
+
+    /// The number of bytes the report was truncated by when the record
+    /// was created. Session logs don't keep it, so it's always zero for
+    /// the records read from them.
+    pub fn truncated_bytes(&self) -> usize {
+        self.truncated_bytes as usize
+    }
+
+    /// The report, of the size of the reports of its direction.
+    pub fn report(&self) -> &[u8] {
+        &self.report[..self.direction.report_size()]
+    }
+
+    pub fn output_report(&self) -> Option<AuraOutputReport> {
+        (self.direction == SessionDirection::Output).then_some(self.report)
+    }
+
+    pub fn input_report(&self) -> Option<AuraInputReport> {
+        let mut report: AuraInputReport = [0; AURA_INPUT_REPORT_SIZE];
+        report.copy_from_slice(&self.report[..AURA_INPUT_REPORT_SIZE]);
+        (self.direction == SessionDirection::Input).then_some(report)
+    }
+
+    /// Encodes the record, given the timestamp of the previous one, and
+    /// returns its size. Gaps longer than `u32::MAX` microseconds, a bit
+    /// over an hour, are shortened to that.
+    pub fn encode(
+        &self,
+        previous_timestamp_us: u64,
+        out: &mut [u8],
+    ) -> Result<usize, SessionLogError> {
+        let report = self.report();
+        let len = report.len() - report.iter().rev().take_while(|b| **b == 0).count();
+        let size = RECORD_HEADER_SIZE + len;
+        if out.len() < size {
+            return Err(SessionLogError::BufferFull);
+        }
+
+        let delta = self.timestamp_us.saturating_sub(previous_timestamp_us);
+        out[0] = self.direction as u8;
+        out[1..5].copy_from_slice(&(delta.min(u32::MAX as u64) as u32).to_le_bytes());
+        out[5] = len as u8;
+        out[RECORD_HEADER_SIZE..size].copy_from_slice(&report[..len]);
+        Ok(size)
+    }
+}
+
+/// Writes the header of a session log, and returns its size.
+pub fn encode_session_header(out: &mut [u8]) -> Result<usize, SessionLogError> {
+    if out.len() < SESSION_LOG_HEADER_SIZE {
+        return Err(SessionLogError::BufferFull);
+    }
+
+    out[..SESSION_LOG_MAGIC.len()].copy_from_slice(&SESSION_LOG_MAGIC);
+    out[SESSION_LOG_MAGIC.len()] = SESSION_LOG_VERSION;
+    Ok(SESSION_LOG_HEADER_SIZE)
+}
+
+/// Receives every report exchanged with the host: the output reports as
+/// they are received, and the input reports as they are sent.
+pub trait AuraSessionRecorder {
+    fn record(&mut self, direction: SessionDirection, report: &[u8]);
+}
+
+/// Records a session log into a buffer, timestamping the reports with
+/// a clock in microseconds.
+pub struct SessionLogWriter<'b, C: FnMut() -> u64> {
+    buf: &'b mut [u8],
+    len: usize,
+    last_timestamp_us: u64,
+    dropped_records: u32,
+    clock: C,
+}
+
+impl<'b, C: FnMut() -> u64> SessionLogWriter<'b, C> {
+    pub fn new(buf: &'b mut [u8], clock: C) -> Result<Self, SessionLogError> {
+        let len = encode_session_header(buf)?;
+        Ok(Self {
+            buf,
+            len,
+            last_timestamp_us: 0,
+            dropped_records: 0,
+            clock,
+        })
+    }
+
+    /// Appends a record to the log.
+    pub fn push(&mut self, record: &SessionRecord) -> Result<(), SessionLogError> {
+        self.len += record.encode(self.last_timestamp_us, &mut self.buf[self.len..])?;
+        self.last_timestamp_us = record.timestamp_us;
+        Ok(())
+    }
+
+    /// The log written so far.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+
+    /// Returns the number of reports that didn't fit in the buffer.
+    pub fn dropped_records(&self) -> u32 {
+        self.dropped_records
+    }
+}
+
+impl<C: FnMut() -> u64> AuraSessionRecorder for SessionLogWriter<'_, C> {
+    fn record(&mut self, direction: SessionDirection, report: &[u8]) {
+        let record = SessionRecord::new((self.clock)(), direction, report);
+        if self.push(&record).is_err() {
+            dev_error!("Session log full, dropping record");
+            self.dropped_records = self.dropped_records.wrapping_add(1);
+        }
+    }
+}
+
+/// Reads the records of a session log.
+pub struct SessionLogReader<'l> {
+    data: &'l [u8],
+    last_timestamp_us: u64,
+}
+
+impl<'l> SessionLogReader<'l> {
+    pub fn new(log: &'l [u8]) -> Result<Self, SessionLogError> {
+        if log.len() < SESSION_LOG_HEADER_SIZE {
+            return Err(SessionLogError::Truncated);
+        }
+        if log[..SESSION_LOG_MAGIC.len()] != SESSION_LOG_MAGIC {
+            return Err(SessionLogError::BadMagic);
+        }
+
+        let version = log[SESSION_LOG_MAGIC.len()];
+        if version != SESSION_LOG_VERSION {
+            return Err(SessionLogError::UnsupportedVersion(version));
+        }
+
+        Ok(Self {
+            data: &log[SESSION_LOG_HEADER_SIZE..],
+            last_timestamp_us: 0,
+        })
+    }
+
+    fn read_record(&mut self) -> Result<SessionRecord, SessionLogError> {
+        if self.data.len() < RECORD_HEADER_SIZE {
+            return Err(SessionLogError::Truncated);
+        }
+
+        let direction = match self.data[0] {
+            0 => SessionDirection::Output,
+            1 => SessionDirection::Input,
+            other => return Err(SessionLogError::InvalidDirection(other)),
+        };
+        let delta = u32::from_le_bytes([self.data[1], self.data[2], self.data[3], self.data[4]]);
+        let len = self.data[5];
+        if len as usize > direction.report_size() {
+            return Err(SessionLogError::InvalidLength(len));
+        }
+
+        let size = RECORD_HEADER_SIZE + len as usize;
+        if self.data.len() < size {
+            return Err(SessionLogError::Truncated);
+        }
+
+        self.last_timestamp_us += delta as u64;
+        let record = SessionRecord::new(
+            self.last_timestamp_us,
+            direction,
+            &self.data[RECORD_HEADER_SIZE..size],
+        );
+        self.data = &self.data[size..];
+        Ok(record)
+    }
+}
+
+impl Iterator for SessionLogReader<'_> {
+    type Item = Result<SessionRecord, SessionLogError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.data.is_empty() {
+            return None;
+        }
+
+        let record = self.read_record();
+        if record.is_err() {
+            // Nothing after a broken record can be trusted.
+            self.data = &[];
+        }
+        Some(record)
+    }
+}
+
+/// Something a session can be replayed against: the protocol engine
+/// directly, or the class behind a simulated bus.
+pub trait ReplayTarget {
+    type Error;
+
+    fn send_output_report(&mut self, report: &AuraOutputReport) -> Result<(), Self::Error>;
+
+    /// Returns the next input report sent by the device, if any.
+    fn receive_input_report(&mut self) -> Result<Option<AuraInputReport>, Self::Error>;
+
+    fn poll_next_message(&mut self) -> Option<RogTerminalMessage>;
+}
+
+impl<const Q: usize> ReplayTarget for AuraProtocolEngine<'_, Q> {
+    type Error = Infallible;
+
+    fn send_output_report(&mut self, report: &AuraOutputReport) -> Result<(), Self::Error> {
+        self.handle_output_report(report);
+        Ok(())
+    }
+
+    fn receive_input_report(&mut self) -> Result<Option<AuraInputReport>, Self::Error> {
+        Ok(self.pop_response())
+    }
+
+    fn poll_next_message(&mut self) -> Option<RogTerminalMessage> {
+        AuraProtocolEngine::poll_next_message(self)
+    }
+}
+
+/// Why a replay didn't match its recording. Records and messages are
+/// counted from zero.
+#[derive(Debug)]
+pub enum ReplayError<E> {
+    /// The session log couldn't be read.
+    Log(SessionLogError),
+
+    /// The target failed to take or give a report.
+    Target(E),
+
+    /// The device sent another response than the recorded one. The
+    /// recorded one can be looked up with [`SessionReplayer::records`].
+    ResponseMismatch { record: usize },
+
+    /// The device didn't send the recorded response.
+    MissingResponse { record: usize },
+
+    /// The device sent more responses than recorded.
+    UnexpectedResponse,
+
+    /// The device decoded another message than the expected one.
+    MessageMismatch { message: usize },
+
+    /// The device decoded fewer messages than expected.
+    MissingMessages { decoded: usize },
+
+    /// The device decoded more messages than expected.
+    UnexpectedMessage { message: usize },
+}
+
+/// What was replayed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ReplaySummary {
+    pub output_reports: usize,
+    pub input_reports: usize,
+    pub messages: usize,
+}
+
+/// Replays a session log against a [`ReplayTarget`].
+pub struct SessionReplayer<'l> {
+    log: &'l [u8],
+}
+
+impl<'l> SessionReplayer<'l> {
+    /// Checks the header of a session log to replay.
+    pub fn new(log: &'l [u8]) -> Result<Self, SessionLogError> {
+        SessionLogReader::new(log)?;
+        Ok(Self { log })
+    }
+
+    pub fn records(&self) -> SessionLogReader<'l> {
+        SessionLogReader::new(self.log).expect("The header was already checked")
+    }
+
+    /// Sends the recorded output reports to the target, checking that
+    /// it sends back the recorded input reports, and hands every
+    /// decoded message to a closure.
+    pub fn replay_with<T: ReplayTarget>(
+        &self,
+        target: &mut T,
+        mut on_message: impl FnMut(RogTerminalMessage) -> Result<(), ReplayError<T::Error>>,
+    ) -> Result<ReplaySummary, ReplayError<T::Error>> {
+        let mut summary = ReplaySummary::default();
+
+        for (index, record) in self.records().enumerate() {
+            let record = record.map_err(ReplayError::Log)?;
+
+            if let Some(report) = record.output_report() {
+                target
+                    .send_output_report(&report)
+                    .map_err(ReplayError::Target)?;
+                summary.output_reports += 1;
+
+                while let Some(message) = target.poll_next_message() {
+                    on_message(message)?;
+                    summary.messages += 1;
+                }
+            } else if let Some(expected) = record.input_report() {
+                let actual = target
+                    .receive_input_report()
+                    .map_err(ReplayError::Target)?
+                    .ok_or(ReplayError::MissingResponse { record: index })?;
+                if actual != expected {
+                    return Err(ReplayError::ResponseMismatch { record: index });
+                }
+                summary.input_reports += 1;
+            }
+        }
+
+        if target
+            .receive_input_report()
+            .map_err(ReplayError::Target)?
+            .is_some()
+        {
+            return Err(ReplayError::UnexpectedResponse);
+        }
+
+        Ok(summary)
+    }
+
+    /// Like [`SessionReplayer::replay_with`], also checking that the
+    /// decoded messages are exactly the expected ones.
+    pub fn replay<T: ReplayTarget>(
+        &self,
+        target: &mut T,
+        expected_messages: &[RogTerminalMessage],
+    ) -> Result<ReplaySummary, ReplayError<T::Error>> {
+        let mut expected = expected_messages.iter().enumerate();
+        let summary = self.replay_with(target, |message| match expected.next() {
+            Some((_, expected)) if *expected == message => Ok(()),
+            Some((index, _)) => Err(ReplayError::MessageMismatch { message: index }),
+            None => Err(ReplayError::UnexpectedMessage {
+                message: expected_messages.len(),
+            }),
+        })?;
+
+        if summary.messages < expected_messages.len() {
+            return Err(ReplayError::MissingMessages {
+                decoded: summary.messages,
+            });
+        }
+
+        Ok(summary)
+    }
+}
@@ -0,0 +1,237 @@
+//! Output of the decoded colors to
+//! [smart-leds](https://docs.rs/smart-leds) drivers, like the ones of
+//! WS2812 or SK6812 strips.
+//!
+//! [`SmartLedsOutput`] maps every Aura channel to a
+//! [`SmartLedsWrite`] driver through [`ChannelDrivers`], which is
+//! implemented for arrays of drivers of the same type, and for tuples
+//! of up to five drivers of different types. Channel N goes to the
+//! N-th driver.
+//!
+//! Drivers are expected to take smart-leds RGB colors. Drivers of RGBW
+//! strips, like SK6812 RGBW ones, take [`RGBW`] colors instead, and
+//! have to be wrapped in an [`RgbwDriver`], which lights their white LED
+//! following a [`WhiteExtraction`].
+//!
+//! ```ignore
+//! let mut output = SmartLedsOutput::new((ws2812_ch0, ws2812_ch1));
+//! while let Some(FrameCommitted { channel }) = framebuffer.poll_committed() {
+//!     output.write_framebuffer(&framebuffer, channel)?;
+//! }
+//! ```
+
+use smart_leds_trait::{SmartLedsWrite, White, RGBW};
+
+use crate::aura::RGB8;
+use crate::color::WhiteExtraction;
+use crate::effects::AuraEffectRenderer;
+use crate::framebuffer::AuraFramebuffer;
+
+/// Converts a color to the one used by smart-leds.
+pub fn to_smart_leds(color: RGB8) -> smart_leds_trait::RGB8 {
+    smart_leds_trait::RGB8 {
+        r: color.r,
+        g: color.g,
+        b: color.b,
+    }
+}
+
+/// Converts a color from the one used by smart-leds.
+pub fn from_smart_leds(color: smart_leds_trait::RGB8) -> RGB8 {
+    RGB8 {
+        r: color.r,
+        g: color.g,
+        b: color.b,
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SmartLedsOutputError {
+    /// There is no driver for the channel.
+    UnmappedChannel(u8),
+
+    /// The driver of the channel failed to write the colors.
+    Driver(u8),
+}
+
+/// A mapping from Aura channels to smart-leds drivers.
+pub trait ChannelDrivers {
+    /// Writes the colors to the driver of `channel`.
+    fn write_channel(&mut self, channel: u8, colors: &[RGB8]) -> Result<(), SmartLedsOutputError>;
+}
+
+fn write_driver<D>(driver: &mut D, channel: u8, colors: &[RGB8]) -> Result<(), SmartLedsOutputError>
+where
+    D: SmartLedsWrite,
+    D::Color: From<smart_leds_trait::RGB8>,
+{
+    driver
+        .write(colors.iter().copied().map(to_smart_leds))
+        .map_err(|_| SmartLedsOutputError::Driver(channel))
+}
+
+impl<D, const N: usize> ChannelDrivers for [D; N]
+where
+    D: SmartLedsWrite,
+    D::Color: From<smart_leds_trait::RGB8>,
+{
+    fn write_channel(&mut self, channel: u8, colors: &[RGB8]) -> Result<(), SmartLedsOutputError> {
+        let driver = self
+            .get_mut(channel as usize)
+            .ok_or(SmartLedsOutputError::UnmappedChannel(channel))?;
+        write_driver(driver, channel, colors)
+    }
+}
+
+macro_rules! impl_channel_drivers_for_tuple {
+    ($(($driver:ident, $idx:tt)),+) => {
+        impl<$($driver),+> ChannelDrivers for ($($driver,)+)
+        where
+            $(
+                $driver: SmartLedsWrite,
+                $driver::Color: From<smart_leds_trait::RGB8>,
+            )+
+        {
+            fn write_channel(
+                &mut self,
+                channel: u8,
+                colors: &[RGB8],
+            ) -> Result<(), SmartLedsOutputError> {
+                match channel {
+                    $($idx => write_driver(&mut self.$idx, channel, colors),)+
+                    _ => Err(SmartLedsOutputError::UnmappedChannel(channel)),
+                }
+            }
+        }
+    };
+}
+
+impl_channel_drivers_for_tuple!((D0, 0));
+impl_channel_drivers_for_tuple!((D0, 0), (D1, 1));
+impl_channel_drivers_for_tuple!((D0, 0), (D1, 1), (D2, 2));
+impl_channel_drivers_for_tuple!((D0, 0), (D1, 1), (D2, 2), (D3, 3));
+impl_channel_drivers_for_tuple!((D0, 0), (D1, 1), (D2, 2), (D3, 3), (D4, 4));
+
+/// Adapts a driver of RGBW strips so it takes RGB colors, which are
+/// split into their RGB and white components by a [`WhiteExtraction`].
+pub struct RgbwDriver<D> {
+    driver: D,
+    white: WhiteExtraction,
+}
+
+impl<D> RgbwDriver<D> {
+    pub fn new(driver: D, white: WhiteExtraction) -> Self {
+        Self { driver, white }
+    }
+
+    pub fn driver(&self) -> &D {
+        &self.driver
+    }
+
+    pub fn driver_mut(&mut self) -> &mut D {
+        &mut self.driver
+    }
+
+    pub fn into_driver(self) -> D {
+        self.driver
+    }
+}
+
+impl<D> SmartLedsWrite for RgbwDriver<D>
+where
+    D: SmartLedsWrite,
+    D::Color: From<RGBW<u8>>,
+{
+    type Error = D::Error;
+    type Color = smart_leds_trait::RGB8;
+
+    fn write<T, I>(&mut self, iterator: T) -> Result<(), Self::Error>
+    where
+        T: IntoIterator<Item = I>,
+        I: Into<Self::Color>,
+    {
+        let white = self.white;
+        self.driver.write(iterator.into_iter().map(|color| {
+            let (RGB8 { r, g, b }, w) = white.extract(from_smart_leds(color.into()));
+            RGBW {
+                r,
+                g,
+                b,
+                a: White(w),
+            }
+        }))
+    }
+}
+
+/// Pushes framebuffer or effect renderer output to smart-leds drivers.
+pub struct SmartLedsOutput<M: ChannelDrivers> {
+    drivers: M,
+}
+
+impl<M: ChannelDrivers> SmartLedsOutput<M> {
+    pub fn new(drivers: M) -> Self {
+        Self { drivers }
+    }
+
+    pub fn drivers(&self) -> &M {
+        &self.drivers
+    }
+
+    pub fn drivers_mut(&mut self) -> &mut M {
+        &mut self.drivers
+    }
+
+    pub fn into_drivers(self) -> M {
+        self.drivers
+    }
+
+    /// Writes the colors to the driver of a channel.
+    pub fn write_channel(
+        &mut self,
+        channel: u8,
+        colors: &[RGB8],
+    ) -> Result<(), SmartLedsOutputError> {
+        self.drivers.write_channel(channel, colors)
+    }
+
+    /// Writes the last committed frame of a channel.
+    pub fn write_framebuffer<const CHANNELS: usize, const LEDS: usize>(
+        &mut self,
+        framebuffer: &AuraFramebuffer<CHANNELS, LEDS>,
+        channel: u8,
+    ) -> Result<(), SmartLedsOutputError> {
+        let frame = framebuffer
+            .front(channel)
+            .ok_or(SmartLedsOutputError::UnmappedChannel(channel))?;
+        self.write_channel(channel, frame)
+    }
+
+    /// Writes every frame committed since the last call. Stops at the
+    /// first driver error.
+    pub fn write_committed<const CHANNELS: usize, const LEDS: usize>(
+        &mut self,
+        framebuffer: &mut AuraFramebuffer<CHANNELS, LEDS>,
+    ) -> Result<(), SmartLedsOutputError> {
+        while let Some(committed) = framebuffer.poll_committed() {
+            self.write_framebuffer(framebuffer, committed.channel)?;
+        }
+
+        Ok(())
+    }
+
+    /// Renders an effect at the given tick into `scratch`, and writes it
+    /// to the driver of a channel. For [`crate::aura::AuraEffect::Direct`]
+    /// `scratch` is written as is, so the framebuffer should be used
+    /// instead.
+    pub fn write_effect(
+        &mut self,
+        renderer: &AuraEffectRenderer,
+        tick: u32,
+        scratch: &mut [RGB8],
+        channel: u8,
+    ) -> Result<(), SmartLedsOutputError> {
+        let len = renderer.led_count().min(scratch.len());
+        renderer.render(tick, &mut scratch[..len]);
+        self.write_channel(channel, &scratch[..len])
+    }
+}
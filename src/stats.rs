@@ -0,0 +1,149 @@
+//! Counters of what the device received from the host, and of what went
+//! wrong, readable without the `log` feature.
+
+use usb_device::UsbError;
+
+use crate::aura::{AuraOutputReportType, InvalidReportError};
+
+/// Something that went wrong while talking to the host.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum AuraError {
+    /// The host sent a report that couldn't be decoded.
+    InvalidReport(InvalidReportError),
+
+    /// The host sent a report the device profile doesn't support, with
+    /// its report type.
+    UnsupportedCommand(u8),
+
+    /// The host sent a direct LED update with more LEDs than allowed,
+    /// with the LED count it sent. The update was clamped.
+    LedCountClamped(u8),
+
+    /// A message was discarded because the message queue was full.
+    MessageDropped,
+
+    /// A report was refused because the message queue was full, under
+    /// [`QueueOverflowPolicy::Refuse`](crate::QueueOverflowPolicy::Refuse).
+    ReportRefused,
+
+    /// A response was discarded because the response queue was full.
+    ResponseQueueOverflow,
+
+    /// A report couldn't be read from the HID class.
+    ReportReadFailed(UsbError),
+
+    /// A response couldn't be pushed to the HID class.
+    InputPushFailed(UsbError),
+}
+
+/// Protocol statistics of an
+/// [`AsusRogTerminalHidClass`](crate::AsusRogTerminalHidClass). Every
+/// counter wraps around on overflow.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct AuraStats {
+    pub firmware_version_requests: u32,
+    pub config_table_requests: u32,
+    pub set_effect_reports: u32,
+    pub set_direct_leds_reports: u32,
+    pub set_mode_reports: u32,
+    pub set_effect_colors_reports: u32,
+    pub commit_reports: u32,
+
+    /// Reports with a report ID other than the Aura one.
+    pub bad_report_ids: u32,
+
+    /// Reports with an unknown report type.
+    pub unknown_types: u32,
+
+    /// Reports of a known type that couldn't be decoded anyway, like
+    /// truncated ones or ones with an unknown effect.
+    pub malformed_reports: u32,
+
+    /// Reports of a type not supported by the device profile.
+    pub unsupported_commands: u32,
+
+    /// Direct LED updates whose LED count had to be clamped.
+    pub clamped_led_counts: u32,
+
+    /// Messages discarded because the message queue was full.
+    pub dropped_messages: u32,
+
+    /// Reports refused because the message queue was full.
+    pub refused_reports: u32,
+
+    /// Responses discarded because the response queue was full.
+    pub response_queue_overflows: u32,
+
+    /// Reports that couldn't be read from the HID class.
+    pub failed_report_reads: u32,
+
+    /// Responses that couldn't be pushed to the HID class. The endpoint
+    /// being busy is not a failure, since the push is just retried on
+    /// the next poll.
+    pub failed_input_pushes: u32,
+
+    /// The last thing that went wrong, if anything.
+    pub last_error: Option<AuraError>,
+}
+
+impl AuraStats {
+    /// Returns the number of valid reports received of a type.
+    pub fn reports_received(&self, report_type: AuraOutputReportType) -> u32 {
+        match report_type {
+            AuraOutputReportType::FirmwareVersionRequest => self.firmware_version_requests,
+            AuraOutputReportType::ConfigTableRequest => self.config_table_requests,
+            AuraOutputReportType::SetEffect => self.set_effect_reports,
+            AuraOutputReportType::SetDirectLeds => self.set_direct_leds_reports,
+            AuraOutputReportType::SetMode => self.set_mode_reports,
+            AuraOutputReportType::SetEffectColors => self.set_effect_colors_reports,
+            AuraOutputReportType::Commit => self.commit_reports,
+        }
+    }
+
+    /// Returns the number of valid reports received of any type.
+    pub fn total_reports_received(&self) -> u32 {
+        self.firmware_version_requests
+            .wrapping_add(self.config_table_requests)
+            .wrapping_add(self.set_effect_reports)
+            .wrapping_add(self.set_direct_leds_reports)
+            .wrapping_add(self.set_mode_reports)
+            .wrapping_add(self.set_effect_colors_reports)
+            .wrapping_add(self.commit_reports)
+    }
+
+    pub(crate) fn count_report(&mut self, report_type: AuraOutputReportType) {
+        let counter = match report_type {
+            AuraOutputReportType::FirmwareVersionRequest => &mut self.firmware_version_requests,
+            AuraOutputReportType::ConfigTableRequest => &mut self.config_table_requests,
+            AuraOutputReportType::SetEffect => &mut self.set_effect_reports,
+            AuraOutputReportType::SetDirectLeds => &mut self.set_direct_leds_reports,
+            AuraOutputReportType::SetMode => &mut self.set_mode_reports,
+            AuraOutputReportType::SetEffectColors => &mut self.set_effect_colors_reports,
+            AuraOutputReportType::Commit => &mut self.commit_reports,
+        };
+        *counter = counter.wrapping_add(1);
+    }
+
+    /// Counts an error and remembers it as the last one.
+    pub(crate) fn record_error(&mut self, error: AuraError) {
+        let counter = match error {
+            AuraError::InvalidReport(InvalidReportError::InvalidReportId) => {
+                &mut self.bad_report_ids
+            }
+            AuraError::InvalidReport(InvalidReportError::InvalidReportType) => {
+                &mut self.unknown_types
+            }
+            AuraError::InvalidReport(_) => &mut self.malformed_reports,
+            AuraError::UnsupportedCommand(_) => &mut self.unsupported_commands,
+            AuraError::LedCountClamped(_) => &mut self.clamped_led_counts,
+            AuraError::MessageDropped => &mut self.dropped_messages,
+            AuraError::ReportRefused => &mut self.refused_reports,
+            AuraError::ResponseQueueOverflow => &mut self.response_queue_overflows,
+            AuraError::ReportReadFailed(_) => &mut self.failed_report_reads,
+            AuraError::InputPushFailed(_) => &mut self.failed_input_pushes,
+        };
+        *counter = counter.wrapping_add(1);
+        self.last_error = Some(error);
+    }
+}
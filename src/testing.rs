@@ -0,0 +1,665 @@
+//! In-memory USB bus and simulated host, for exercising
+//! [`AsusRogTerminalHidClass`] without real hardware.
+//!
+//! The [`TestBus`] implements [`UsbBus`] by keeping the packets of
+//! every endpoint in memory, and the [`SimulatedHost`] plays the role
+//! of the host on the other end of it: it enumerates the device, sends
+//! output reports through SET_REPORT control transfers and reads the
+//! input reports from the interrupt endpoint, polling the device as a
+//! real bus would after every packet.
+//!
+//! ```ignore
+//! let alloc = UsbBusAllocator::new(TestBus::new());
+//! let class = AsusRogTerminalHidClass::new_with_defaults(&alloc);
+//! let device = rog_terminal_usb_device_builder(&alloc).build();
+//! let mut host = SimulatedHost::new(device, class);
+//! host.enumerate().unwrap();
+//! ```
+
+use std::sync::Mutex;
+use std::vec::Vec;
+
+use usb_device::bus::{PollResult, UsbBus};
+use usb_device::device::{UsbDevice, UsbDeviceState};
+use usb_device::endpoint::{EndpointAddress, EndpointType};
+use usb_device::{UsbDirection, UsbError};
+
+use crate::aura::constants::{AURA_INPUT_REPORT_SIZE, AURA_OUTPUT_REPORT_SIZE};
+use crate::aura::{AuraInputReport, AuraOutputReport};
+use crate::{AsusRogTerminalHidClass, RogTerminalMessage, DEFAULT_MESSAGE_QUEUE_CAPACITY};
+
+const MAX_ENDPOINTS: usize = 16;
+
+/// The address assigned by the simulated host during enumeration.
+pub const TEST_DEVICE_ADDRESS: u8 = 5;
+
+/// Upper bound of device polls performed after each packet, so a
+/// misbehaving class can't hang a test.
+const MAX_POLLS_PER_PACKET: usize = 16;
+
+#[derive(Default)]
+struct EndpointState {
+    allocated: bool,
+    max_packet_size: u16,
+    packet: Option<Vec<u8>>,
+    stalled: bool,
+}
+
+#[derive(Default)]
+struct BusState {
+    out_eps: [EndpointState; MAX_ENDPOINTS],
+    in_eps: [EndpointState; MAX_ENDPOINTS],
+    setup_pending: bool,
+    in_complete: u16,
+    reset_pending: bool,
+    address: u8,
+}
+
+impl BusState {
+    fn endpoint(&mut self, ep_addr: EndpointAddress) -> &mut EndpointState {
+        match ep_addr.direction() {
+            UsbDirection::Out => &mut self.out_eps[ep_addr.index()],
+            UsbDirection::In => &mut self.in_eps[ep_addr.index()],
+        }
+    }
+}
+
+/// An in-memory [`UsbBus`] implementation, meant to be driven by a
+/// [`SimulatedHost`].
+pub struct TestBus {
+    state: Mutex<BusState>,
+}
+
+impl TestBus {
+    pub fn new() -> Self {
+        Self {
+            state: Mutex::new(BusState::default()),
+        }
+    }
+
+    fn with_state<R>(&self, f: impl FnOnce(&mut BusState) -> R) -> R {
+        f(&mut self.state.lock().unwrap())
+    }
+
+    /// Returns the address assigned to the device by the host.
+    pub fn address(&self) -> u8 {
+        self.with_state(|state| state.address)
+    }
+
+    fn host_reset(&self) {
+        self.with_state(|state| state.reset_pending = true);
+    }
+
+    fn host_setup(&self, setup: [u8; 8]) {
+        self.with_state(|state| {
+            // A SETUP packet always overrides whatever the control
+            // endpoint was doing, and clears its STALL condition.
+            state.out_eps[0].packet = Some(setup.to_vec());
+            state.out_eps[0].stalled = false;
+            state.in_eps[0].packet = None;
+            state.in_eps[0].stalled = false;
+            state.in_complete &= !1;
+            state.setup_pending = true;
+        });
+    }
+
+    fn host_out(&self, index: usize, data: &[u8]) -> Result<(), TestHostError> {
+        self.with_state(|state| {
+            let ep = &mut state.out_eps[index];
+            if ep.stalled {
+                return Err(TestHostError::Stalled);
+            }
+
+            if ep.packet.is_some() {
+                return Err(TestHostError::Nak);
+            }
+
+            ep.packet = Some(data.to_vec());
+            Ok(())
+        })
+    }
+
+    fn host_in(&self, index: usize) -> Result<Vec<u8>, TestHostError> {
+        self.with_state(|state| {
+            let ep = &mut state.in_eps[index];
+            if ep.stalled {
+                return Err(TestHostError::Stalled);
+            }
+
+            let packet = ep.packet.take().ok_or(TestHostError::Nak)?;
+            state.in_complete |= 1 << index;
+            Ok(packet)
+        })
+    }
+
+    fn max_packet_size(&self, ep_addr: EndpointAddress) -> u16 {
+        self.with_state(|state| state.endpoint(ep_addr).max_packet_size)
+    }
+
+    fn has_events(&self) -> bool {
+        self.with_state(|state| {
+            state.reset_pending
+                || state.in_complete != 0
+                || state.out_eps.iter().any(|ep| ep.packet.is_some())
+        })
+    }
+}
+
+impl Default for TestBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl UsbBus for TestBus {
+    fn alloc_ep(
+        &mut self,
+        ep_dir: UsbDirection,
+        ep_addr: Option<EndpointAddress>,
+        _ep_type: EndpointType,
+        max_packet_size: u16,
+        _interval: u8,
+    ) -> usb_device::Result<EndpointAddress> {
+        let state = self.state.get_mut().unwrap();
+        let eps = match ep_dir {
+            UsbDirection::Out => &mut state.out_eps,
+            UsbDirection::In => &mut state.in_eps,
+        };
+
+        let index = match ep_addr {
+            Some(addr) if eps[addr.index()].allocated => return Err(UsbError::InvalidEndpoint),
+            Some(addr) => addr.index(),
+            None => (1..MAX_ENDPOINTS)
+                .find(|&i| !eps[i].allocated)
+                .ok_or(UsbError::EndpointOverflow)?,
+        };
+
+        eps[index] = EndpointState {
+            allocated: true,
+            max_packet_size,
+            packet: None,
+            stalled: false,
+        };
+
+        Ok(EndpointAddress::from_parts(index, ep_dir))
+    }
+
+    fn enable(&mut self) {}
+
+    fn reset(&self) {
+        self.with_state(|state| {
+            for ep in state.out_eps.iter_mut().chain(state.in_eps.iter_mut()) {
+                ep.packet = None;
+                ep.stalled = false;
+            }
+            state.setup_pending = false;
+            state.in_complete = 0;
+            state.address = 0;
+        })
+    }
+
+    fn set_device_address(&self, addr: u8) {
+        self.with_state(|state| state.address = addr)
+    }
+
+    fn write(&self, ep_addr: EndpointAddress, buf: &[u8]) -> usb_device::Result<usize> {
+        self.with_state(|state| {
+            let ep = state.endpoint(ep_addr);
+            if !ep.allocated || ep_addr.direction() != UsbDirection::In {
+                return Err(UsbError::InvalidEndpoint);
+            }
+
+            if ep.packet.is_some() {
+                return Err(UsbError::WouldBlock);
+            }
+
+            if buf.len() > ep.max_packet_size as usize {
+                return Err(UsbError::BufferOverflow);
+            }
+
+            ep.packet = Some(buf.to_vec());
+            Ok(buf.len())
+        })
+    }
+
+    fn read(&self, ep_addr: EndpointAddress, buf: &mut [u8]) -> usb_device::Result<usize> {
+        self.with_state(|state| {
+            let index = ep_addr.index();
+            let ep = state.endpoint(ep_addr);
+            if !ep.allocated || ep_addr.direction() != UsbDirection::Out {
+                return Err(UsbError::InvalidEndpoint);
+            }
+
+            let Some(packet) = ep.packet.as_ref() else {
+                return Err(UsbError::WouldBlock);
+            };
+
+            if packet.len() > buf.len() {
+                return Err(UsbError::BufferOverflow);
+            }
+
+            let len = packet.len();
+            buf[..len].copy_from_slice(packet);
+            ep.packet = None;
+            if index == 0 {
+                state.setup_pending = false;
+            }
+
+            Ok(len)
+        })
+    }
+
+    fn set_stalled(&self, ep_addr: EndpointAddress, stalled: bool) {
+        self.with_state(|state| state.endpoint(ep_addr).stalled = stalled)
+    }
+
+    fn is_stalled(&self, ep_addr: EndpointAddress) -> bool {
+        self.with_state(|state| state.endpoint(ep_addr).stalled)
+    }
+
+    fn suspend(&self) {}
+
+    fn resume(&self) {}
+
+    fn poll(&self) -> PollResult {
+        self.with_state(|state| {
+            if state.reset_pending {
+                state.reset_pending = false;
+                return PollResult::Reset;
+            }
+
+            let mut ep_out = 0;
+            let mut ep_setup = 0;
+            for (i, ep) in state.out_eps.iter().enumerate() {
+                if ep.packet.is_some() {
+                    if i == 0 && state.setup_pending {
+                        ep_setup |= 1 << i;
+                    } else {
+                        ep_out |= 1 << i;
+                    }
+                }
+            }
+
+            let ep_in_complete = state.in_complete;
+            state.in_complete = 0;
+
+            if ep_out == 0 && ep_setup == 0 && ep_in_complete == 0 {
+                PollResult::None
+            } else {
+                PollResult::Data {
+                    ep_out,
+                    ep_in_complete,
+                    ep_setup,
+                }
+            }
+        })
+    }
+}
+
+/// The errors a [`SimulatedHost`] can run into while talking to the
+/// device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TestHostError {
+    /// The device stalled the endpoint.
+    Stalled,
+
+    /// The device didn't have data ready, or wasn't ready to receive
+    /// more data.
+    Nak,
+
+    /// The device sent something that doesn't follow the USB spec.
+    Protocol,
+}
+
+/// What the simulated host learnt about the device during enumeration.
+#[derive(Debug, Clone)]
+pub struct EnumeratedDevice {
+    pub vendor_id: u16,
+    pub product_id: u16,
+    pub device_descriptor: Vec<u8>,
+    pub configuration_descriptor: Vec<u8>,
+    pub report_descriptor: Vec<u8>,
+    pub hid_interface: u8,
+    pub hid_in_endpoint: u8,
+}
+
+/// A host connected to a [`TestBus`], driving an
+/// [`AsusRogTerminalHidClass`] as the OS and the host application
+/// would do.
+pub struct SimulatedHost<'a, const Q: usize = DEFAULT_MESSAGE_QUEUE_CAPACITY> {
+    device: UsbDevice<'a, TestBus>,
+    class: AsusRogTerminalHidClass<'a, TestBus, Q>,
+    enumerated: Option<EnumeratedDevice>,
+}
+
+const DESCRIPTOR_TYPE_DEVICE: u8 = 0x01;
+const DESCRIPTOR_TYPE_CONFIGURATION: u8 = 0x02;
+const DESCRIPTOR_TYPE_INTERFACE: u8 = 0x04;
+const DESCRIPTOR_TYPE_ENDPOINT: u8 = 0x05;
+const DESCRIPTOR_TYPE_HID: u8 = 0x21;
+const DESCRIPTOR_TYPE_HID_REPORT: u8 = 0x22;
+
+const REQUEST_SET_ADDRESS: u8 = 0x05;
+const REQUEST_GET_DESCRIPTOR: u8 = 0x06;
+const REQUEST_SET_CONFIGURATION: u8 = 0x09;
+const REQUEST_HID_SET_REPORT: u8 = 0x09;
+
+const HID_REPORT_TYPE_OUTPUT: u8 = 0x02;
+const HID_INTERFACE_CLASS: u8 = 0x03;
+
+fn setup_packet(request_type: u8, request: u8, value: u16, index: u16, length: u16) -> [u8; 8] {
+    let value = value.to_le_bytes();
+    let index = index.to_le_bytes();
+    let length = length.to_le_bytes();
+    [
+        request_type,
+        request,
+        value[0],
+        value[1],
+        index[0],
+        index[1],
+        length[0],
+        length[1],
+    ]
+}
+
+impl<'a, const Q: usize> SimulatedHost<'a, Q> {
+    pub fn new(
+        device: UsbDevice<'a, TestBus>,
+        class: AsusRogTerminalHidClass<'a, TestBus, Q>,
+    ) -> Self {
+        Self {
+            device,
+            class,
+            enumerated: None,
+        }
+    }
+
+    pub fn device(&self) -> &UsbDevice<'a, TestBus> {
+        &self.device
+    }
+
+    pub fn class(&self) -> &AsusRogTerminalHidClass<'a, TestBus, Q> {
+        &self.class
+    }
+
+    pub fn class_mut(&mut self) -> &mut AsusRogTerminalHidClass<'a, TestBus, Q> {
+        &mut self.class
+    }
+
+    /// Returns what was learnt about the device during
+    /// [`SimulatedHost::enumerate`], if it was already enumerated.
+    pub fn enumerated(&self) -> Option<&EnumeratedDevice> {
+        self.enumerated.as_ref()
+    }
+
+    /// Polls the device until the bus has no more pending events.
+    pub fn poll(&mut self) {
+        for _ in 0..MAX_POLLS_PER_PACKET {
+            self.device.poll(&mut [&mut self.class]);
+            if !self.device.bus().has_events() {
+                break;
+            }
+        }
+    }
+
+    fn ep0_max_packet_size(&self) -> usize {
+        self.device
+            .bus()
+            .max_packet_size(EndpointAddress::from_parts(0, UsbDirection::In)) as usize
+    }
+
+    /// Performs a control transfer with an OUT data stage (or no data
+    /// stage at all).
+    pub fn control_out(&mut self, setup: [u8; 8], data: &[u8]) -> Result<(), TestHostError> {
+        self.device.bus().host_setup(setup);
+        self.poll();
+
+        for chunk in data.chunks(self.ep0_max_packet_size()) {
+            self.device.bus().host_out(0, chunk)?;
+            self.poll();
+        }
+
+        // Status stage: the device acknowledges with a zero-length IN
+        // packet.
+        let status = self.device.bus().host_in(0)?;
+        self.poll();
+
+        if status.is_empty() {
+            Ok(())
+        } else {
+            Err(TestHostError::Protocol)
+        }
+    }
+
+    /// Performs a control transfer with an IN data stage, returning at
+    /// most `length` bytes.
+    pub fn control_in(&mut self, setup: [u8; 8], length: u16) -> Result<Vec<u8>, TestHostError> {
+        let max_packet_size = self.ep0_max_packet_size();
+        self.device.bus().host_setup(setup);
+        self.poll();
+
+        let mut data = Vec::new();
+        loop {
+            let packet = self.device.bus().host_in(0)?;
+            self.poll();
+
+            data.extend_from_slice(&packet);
+            if packet.len() < max_packet_size || data.len() >= length as usize {
+                break;
+            }
+        }
+
+        // Status stage: the host acknowledges with a zero-length OUT
+        // packet.
+        self.device.bus().host_out(0, &[])?;
+        self.poll();
+
+        data.truncate(length as usize);
+        Ok(data)
+    }
+
+    fn get_descriptor(
+        &mut self,
+        request_type: u8,
+        descriptor_type: u8,
+        index: u16,
+        length: u16,
+    ) -> Result<Vec<u8>, TestHostError> {
+        self.control_in(
+            setup_packet(
+                request_type,
+                REQUEST_GET_DESCRIPTOR,
+                (descriptor_type as u16) << 8,
+                index,
+                length,
+            ),
+            length,
+        )
+    }
+
+    /// Resets and enumerates the device: assigns it an address, reads
+    /// its descriptors and selects its configuration.
+    pub fn enumerate(&mut self) -> Result<&EnumeratedDevice, TestHostError> {
+        self.device.bus().host_reset();
+        self.poll();
+
+        // Like most operating systems, first ask for the beginning of
+        // the device descriptor to learn the control max packet size.
+        self.get_descriptor(0x80, DESCRIPTOR_TYPE_DEVICE, 0, 64)?;
+
+        self.control_out(
+            setup_packet(0x00, REQUEST_SET_ADDRESS, TEST_DEVICE_ADDRESS as u16, 0, 0),
+            &[],
+        )?;
+        if self.device.bus().address() != TEST_DEVICE_ADDRESS {
+            return Err(TestHostError::Protocol);
+        }
+
+        let device_descriptor = self.get_descriptor(0x80, DESCRIPTOR_TYPE_DEVICE, 0, 18)?;
+        if device_descriptor.len() != 18 {
+            return Err(TestHostError::Protocol);
+        }
+
+        let config_header = self.get_descriptor(0x80, DESCRIPTOR_TYPE_CONFIGURATION, 0, 9)?;
+        if config_header.len() != 9 {
+            return Err(TestHostError::Protocol);
+        }
+        let total_length = u16::from_le_bytes([config_header[2], config_header[3]]);
+        let configuration_descriptor =
+            self.get_descriptor(0x80, DESCRIPTOR_TYPE_CONFIGURATION, 0, total_length)?;
+
+        let (hid_interface, report_descriptor_len, hid_in_endpoint) =
+            find_hid_interface(&configuration_descriptor).ok_or(TestHostError::Protocol)?;
+
+        self.control_out(
+            setup_packet(
+                0x00,
+                REQUEST_SET_CONFIGURATION,
+                config_header[5] as u16,
+                0,
+                0,
+            ),
+            &[],
+        )?;
+        if self.device.state() != UsbDeviceState::Configured {
+            return Err(TestHostError::Protocol);
+        }
+
+        let report_descriptor = self.get_descriptor(
+            0x81,
+            DESCRIPTOR_TYPE_HID_REPORT,
+            hid_interface as u16,
+            report_descriptor_len,
+        )?;
+
+        self.enumerated = Some(EnumeratedDevice {
+            vendor_id: u16::from_le_bytes([device_descriptor[8], device_descriptor[9]]),
+            product_id: u16::from_le_bytes([device_descriptor[10], device_descriptor[11]]),
+            device_descriptor,
+            configuration_descriptor,
+            report_descriptor,
+            hid_interface,
+            hid_in_endpoint,
+        });
+
+        Ok(self.enumerated.as_ref().unwrap())
+    }
+
+    fn enumerated_or_err(&self) -> Result<&EnumeratedDevice, TestHostError> {
+        self.enumerated.as_ref().ok_or(TestHostError::Protocol)
+    }
+
+    /// Sends an output report to the device through a SET_REPORT
+    /// request, like hidapi does for devices without an OUT endpoint.
+    pub fn send_output_report(&mut self, report: &AuraOutputReport) -> Result<(), TestHostError> {
+        let interface = self.enumerated_or_err()?.hid_interface;
+        self.control_out(
+            setup_packet(
+                0x21,
+                REQUEST_HID_SET_REPORT,
+                ((HID_REPORT_TYPE_OUTPUT as u16) << 8) | report[0] as u16,
+                interface as u16,
+                AURA_OUTPUT_REPORT_SIZE as u16,
+            ),
+            report,
+        )
+    }
+
+    /// Reads the next input report sent by the device through its
+    /// interrupt endpoint, if any.
+    pub fn read_input_report(&mut self) -> Result<Option<AuraInputReport>, TestHostError> {
+        let endpoint = self.enumerated_or_err()?.hid_in_endpoint;
+        self.poll();
+
+        let packet = match self.device.bus().host_in(endpoint as usize & 0x0f) {
+            Ok(packet) => packet,
+            Err(TestHostError::Nak) => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        self.poll();
+
+        if packet.len() != AURA_INPUT_REPORT_SIZE {
+            return Err(TestHostError::Protocol);
+        }
+
+        let mut report: AuraInputReport = [0; AURA_INPUT_REPORT_SIZE];
+        report.copy_from_slice(&packet);
+        Ok(Some(report))
+    }
+}
+
+/// Looks for the first HID interface in a configuration descriptor,
+/// returning its number, the length of its report descriptor and the
+/// address of its interrupt IN endpoint.
+fn find_hid_interface(descriptor: &[u8]) -> Option<(u8, u16, u8)> {
+    let mut interface = None;
+    let mut report_descriptor_len = None;
+    let mut i = 0;
+
+    while i + 2 <= descriptor.len() {
+        let len = descriptor[i] as usize;
+        if len < 2 || i + len > descriptor.len() {
+            return None;
+        }
+
+        let desc = &descriptor[i..i + len];
+        match desc[1] {
+            DESCRIPTOR_TYPE_INTERFACE if len >= 9 => {
+                if interface.is_some() && report_descriptor_len.is_some() {
+                    // Left the HID interface without finding its
+                    // endpoint.
+                    return None;
+                }
+                interface = (desc[5] == HID_INTERFACE_CLASS).then_some(desc[2]);
+            }
+            DESCRIPTOR_TYPE_HID if len >= 9 && interface.is_some() => {
+                report_descriptor_len = Some(u16::from_le_bytes([desc[7], desc[8]]));
+            }
+            // Interrupt IN endpoint of the HID interface.
+            DESCRIPTOR_TYPE_ENDPOINT
+                if len >= 7
+                    && interface.is_some()
+                    && desc[2] & 0x80 != 0
+                    && desc[3] & 0x03 == 0x03 =>
+            {
+                return Some((interface?, report_descriptor_len?, desc[2]));
+            }
+            _ => {}
+        }
+
+        i += len;
+    }
+
+    None
+}
+
+#[cfg(feature = "host")]
+impl<const Q: usize> crate::host::AuraTransport for SimulatedHost<'_, Q> {
+    type Error = TestHostError;
+
+    fn send_output_report(&mut self, report: &AuraOutputReport) -> Result<(), Self::Error> {
+        SimulatedHost::send_output_report(self, report)
+    }
+
+    fn receive_input_report(&mut self, report: &mut AuraInputReport) -> Result<(), Self::Error> {
+        *report = self.read_input_report()?.ok_or(TestHostError::Nak)?;
+        Ok(())
+    }
+}
+
+impl<const Q: usize> crate::session::ReplayTarget for SimulatedHost<'_, Q> {
+    type Error = TestHostError;
+
+    fn send_output_report(&mut self, report: &AuraOutputReport) -> Result<(), Self::Error> {
+        SimulatedHost::send_output_report(self, report)
+    }
+
+    fn receive_input_report(&mut self) -> Result<Option<AuraInputReport>, Self::Error> {
+        self.read_input_report()
+    }
+
+    fn poll_next_message(&mut self) -> Option<RogTerminalMessage> {
+        self.class_mut().poll_next_message()
+    }
+}
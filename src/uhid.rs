@@ -0,0 +1,249 @@
+//! Virtual HID device on Linux, through `/dev/uhid`, for running the
+//! emulation without a microcontroller.
+//!
+//! The kernel exposes the [`UhidDevice`] as any other HID device, with
+//! the descriptor and the VID/PID of a [`DeviceProfile`], so host
+//! applications like OpenRGB can detect and drive it. The reports they
+//! send are handed to an [`AuraProtocolEngine`], and its responses are
+//! sent back as input reports.
+//!
+//! Creating the device usually requires root, or write access to
+//! `/dev/uhid`.
+//!
+//! ```ignore
+//! let mut device = UhidDevice::create(&DeviceProfile::ROG_TERMINAL, AuraProtocolEngine::default())?;
+//! loop {
+//!     device.process_event()?;
+//!     while let Some(message) = device.engine_mut().poll_next_message() {
+//!         ...
+//!     }
+//! }
+//! ```
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+use crate::aura::constants::{AURA_HID_REPORT_ID, AURA_OUTPUT_REPORT_SIZE};
+use crate::engine::AuraProtocolEngine;
+use crate::profile::DeviceProfile;
+use crate::DEFAULT_MESSAGE_QUEUE_CAPACITY;
+
+/// The path of the UHID character device.
+pub const UHID_PATH: &str = "/dev/uhid";
+
+// The uhid_event_type values of linux/uhid.h.
+const UHID_DESTROY: u32 = 1;
+const UHID_START: u32 = 2;
+const UHID_STOP: u32 = 3;
+const UHID_OPEN: u32 = 4;
+const UHID_CLOSE: u32 = 5;
+const UHID_OUTPUT: u32 = 6;
+const UHID_GET_REPORT: u32 = 9;
+const UHID_GET_REPORT_REPLY: u32 = 10;
+const UHID_CREATE2: u32 = 11;
+const UHID_INPUT2: u32 = 12;
+const UHID_SET_REPORT: u32 = 13;
+const UHID_SET_REPORT_REPLY: u32 = 14;
+
+const UHID_OUTPUT_REPORT: u8 = 1;
+const UHID_DATA_MAX: usize = 4096;
+
+// sizeof(struct uhid_event): the event type, followed by the union of
+// every request. The kernel reads and writes events whole.
+const UHID_EVENT_SIZE: usize = 4380;
+
+const BUS_USB: u16 = 0x03;
+const EIO: u16 = 5;
+
+/// What the kernel notified the device of.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UhidEvent {
+    /// The kernel bound a HID driver to the device.
+    Started,
+
+    /// The kernel unbound the HID driver from the device.
+    Stopped,
+
+    /// A host application opened the device.
+    Opened,
+
+    /// The last host application closed the device.
+    Closed,
+
+    /// The host sent a report, which was handed to the engine.
+    Report,
+
+    /// The host asked for a report, which isn't supported by the Aura
+    /// protocol, and was refused.
+    GetReport,
+
+    /// An event this device doesn't care about.
+    Other(u32),
+}
+
+/// A virtual HID device running the Aura protocol.
+pub struct UhidDevice<'a, const Q: usize = DEFAULT_MESSAGE_QUEUE_CAPACITY> {
+    file: File,
+    engine: AuraProtocolEngine<'a, Q>,
+}
+
+impl<'a, const Q: usize> UhidDevice<'a, Q> {
+    /// Creates a device with the descriptor, VID/PID and product name
+    /// of a profile.
+    pub fn create(profile: &DeviceProfile, engine: AuraProtocolEngine<'a, Q>) -> io::Result<Self> {
+        Self::create_at(UHID_PATH, profile, engine)
+    }
+
+    /// Like [`UhidDevice::create`], with the UHID character device at
+    /// another path.
+    pub fn create_at(
+        path: impl AsRef<Path>,
+        profile: &DeviceProfile,
+        engine: AuraProtocolEngine<'a, Q>,
+    ) -> io::Result<Self> {
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        let mut device = Self { file, engine };
+
+        let mut event = [0; UHID_EVENT_SIZE];
+        event[..4].copy_from_slice(&UHID_CREATE2.to_ne_bytes());
+        let req = &mut event[4..];
+
+        // The name is NUL terminated.
+        let name = profile.product.as_bytes();
+        let name_len = name.len().min(127);
+        req[..name_len].copy_from_slice(&name[..name_len]);
+
+        let descriptor = profile.hid_descriptor;
+        if descriptor.len() > UHID_DATA_MAX {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "HID descriptor too long",
+            ));
+        }
+
+        req[256..258].copy_from_slice(&(descriptor.len() as u16).to_ne_bytes());
+        req[258..260].copy_from_slice(&BUS_USB.to_ne_bytes());
+        req[260..264].copy_from_slice(&(profile.vendor_id as u32).to_ne_bytes());
+        req[264..268].copy_from_slice(&(profile.product_id as u32).to_ne_bytes());
+        // Version and country stay zero.
+        req[276..276 + descriptor.len()].copy_from_slice(descriptor);
+
+        device.write_event(&event)?;
+        Ok(device)
+    }
+
+    pub fn engine(&self) -> &AuraProtocolEngine<'a, Q> {
+        &self.engine
+    }
+
+    pub fn engine_mut(&mut self) -> &mut AuraProtocolEngine<'a, Q> {
+        &mut self.engine
+    }
+
+    fn write_event(&mut self, event: &[u8; UHID_EVENT_SIZE]) -> io::Result<()> {
+        self.file.write_all(event)
+    }
+
+    /// Waits for the next event of the kernel and handles it, sending
+    /// the responses of the engine, if any.
+    pub fn process_event(&mut self) -> io::Result<UhidEvent> {
+        let mut event = [0; UHID_EVENT_SIZE];
+        let len = self.file.read(&mut event)?;
+        if len < 4 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "UHID event too short",
+            ));
+        }
+
+        let event_type = u32::from_ne_bytes(event[..4].try_into().unwrap());
+        let req = &event[4..];
+        let result = match event_type {
+            UHID_START => UhidEvent::Started,
+            UHID_STOP => UhidEvent::Stopped,
+            UHID_OPEN => UhidEvent::Opened,
+            UHID_CLOSE => UhidEvent::Closed,
+            UHID_OUTPUT => {
+                // struct uhid_output_req: data, size, rtype.
+                let size = u16::from_ne_bytes([req[UHID_DATA_MAX], req[UHID_DATA_MAX + 1]]);
+                if req[UHID_DATA_MAX + 2] == UHID_OUTPUT_REPORT {
+                    self.handle_report(&req[..(size as usize).min(UHID_DATA_MAX)]);
+                }
+                UhidEvent::Report
+            }
+            UHID_SET_REPORT => {
+                // struct uhid_set_report_req: id, rnum, rtype, size, data.
+                let id = [req[0], req[1], req[2], req[3]];
+                let rtype = req[5];
+                let size = u16::from_ne_bytes([req[6], req[7]]) as usize;
+                let accepted = rtype == UHID_OUTPUT_REPORT
+                    && self.handle_report(&req[8..8 + size.min(UHID_DATA_MAX)]);
+
+                let mut reply = [0; UHID_EVENT_SIZE];
+                reply[..4].copy_from_slice(&UHID_SET_REPORT_REPLY.to_ne_bytes());
+                reply[4..8].copy_from_slice(&id);
+                let err = if accepted { 0 } else { EIO };
+                reply[8..10].copy_from_slice(&err.to_ne_bytes());
+                self.write_event(&reply)?;
+                UhidEvent::Report
+            }
+            UHID_GET_REPORT => {
+                // The kernel waits for a reply, so refuse it right away.
+                let mut reply = [0; UHID_EVENT_SIZE];
+                reply[..4].copy_from_slice(&UHID_GET_REPORT_REPLY.to_ne_bytes());
+                reply[4..8].copy_from_slice(&req[..4]);
+                reply[8..10].copy_from_slice(&EIO.to_ne_bytes());
+                self.write_event(&reply)?;
+                UhidEvent::GetReport
+            }
+            other => UhidEvent::Other(other),
+        };
+
+        self.send_responses()?;
+        Ok(result)
+    }
+
+    /// Hands a report, including its report ID, to the engine. Returns
+    /// false if the report was ignored or refused.
+    fn handle_report(&mut self, data: &[u8]) -> bool {
+        if data.first() != Some(&AURA_HID_REPORT_ID) || data.len() > AURA_OUTPUT_REPORT_SIZE {
+            dev_error!("Ignoring report of {} bytes", data.len());
+            return false;
+        }
+
+        if !self.engine.admit_report() {
+            return false;
+        }
+
+        self.engine.handle_output_slice(data);
+        true
+    }
+
+    /// Sends the responses queued by the engine as input reports.
+    /// [`UhidDevice::process_event`] already does it, but responses
+    /// queued by the application, like with
+    /// [`AuraProtocolEngine::queue_input_report`], need it.
+    pub fn send_responses(&mut self) -> io::Result<()> {
+        while let Some(report) = self.engine.peek_response() {
+            // struct uhid_input2_req: size, data.
+            let mut event = [0; UHID_EVENT_SIZE];
+            event[..4].copy_from_slice(&UHID_INPUT2.to_ne_bytes());
+            event[4..6].copy_from_slice(&(report.len() as u16).to_ne_bytes());
+            event[6..6 + report.len()].copy_from_slice(&report);
+            self.write_event(&event)?;
+            self.engine.pop_response();
+        }
+
+        Ok(())
+    }
+}
+
+impl<const Q: usize> Drop for UhidDevice<'_, Q> {
+    fn drop(&mut self) {
+        let mut event = [0; UHID_EVENT_SIZE];
+        event[..4].copy_from_slice(&UHID_DESTROY.to_ne_bytes());
+        // Closing the file destroys the device anyway.
+        self.write_event(&event).ok();
+    }
+}
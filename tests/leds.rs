@@ -0,0 +1,440 @@
+use asus_rog_terminal_usb_device::aura::{
+    AuraChannel, AuraEffect, AuraEffectDirection, AuraEffectParams, RGB8,
+};
+use asus_rog_terminal_usb_device::color::{
+    ChannelColorConfig, ColorOrder, ColorPipeline, WhiteBalance, WhiteExtraction, GAMMA_2_2,
+    GAMMA_2_8, GAMMA_LINEAR,
+};
+use asus_rog_terminal_usb_device::effects::{render, AuraEffectRenderer};
+use asus_rog_terminal_usb_device::framebuffer::{AuraFramebuffer, FrameCommitted};
+use asus_rog_terminal_usb_device::power::{LedPowerModel, PowerLimiter};
+use asus_rog_terminal_usb_device::smart_leds::{
+    to_smart_leds, RgbwDriver, SmartLedsOutput, SmartLedsOutputError,
+};
+use asus_rog_terminal_usb_device::RogTerminalMessage;
+use smart_leds_trait::{SmartLedsWrite, White, RGBW};
+
+const BLACK: RGB8 = RGB8 { r: 0, g: 0, b: 0 };
+
+fn rgb(r: u8, g: u8, b: u8) -> RGB8 {
+    RGB8 { r, g, b }
+}
+
+fn params(color: RGB8) -> AuraEffectParams {
+    // At the slowest speed, a cycle lasts 4000 ticks.
+    AuraEffectParams {
+        color,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn renders_static_effects() {
+    let renderer = AuraEffectRenderer::new(AuraEffect::Static, params(rgb(10, 20, 30)), 3);
+    let mut leds = [rgb(1, 1, 1); 5];
+    for tick in [0, 1234, 99_999] {
+        renderer.render(tick, &mut leds);
+        assert_eq!(
+            leds,
+            [
+                rgb(10, 20, 30),
+                rgb(10, 20, 30),
+                rgb(10, 20, 30),
+                BLACK,
+                BLACK
+            ]
+        );
+    }
+
+    let dimmed = AuraEffectParams {
+        brightness: 0x80,
+        ..params(rgb(255, 255, 0))
+    };
+    render(AuraEffect::Static, &dimmed, 0, &mut leds);
+    assert_eq!(leds, [rgb(128, 128, 0); 5]);
+}
+
+#[test]
+fn renders_breathing_peak_and_trough() {
+    let params = params(rgb(255, 100, 0));
+    let mut leds = [BLACK; 2];
+
+    render(AuraEffect::Breathing, &params, 0, &mut leds);
+    assert_eq!(leds, [BLACK; 2]);
+
+    // Half a cycle in, the brightness is at its peak of 253.
+    render(AuraEffect::Breathing, &params, 2000, &mut leds);
+    assert_eq!(leds, [rgb(253, 99, 0); 2]);
+
+    render(AuraEffect::Breathing, &params, 1000, &mut leds);
+    assert!(leds[0].r > 0 && leds[0].r < 253);
+
+    render(AuraEffect::Breathing, &params, 4000, &mut leds);
+    assert_eq!(leds, [BLACK; 2]);
+}
+
+#[test]
+fn renders_rainbow_hue_steps() {
+    let mut params = params(BLACK);
+    let mut start = [BLACK; 4];
+    render(AuraEffect::Rainbow, &params, 0, &mut start);
+    // The hue advances by a quarter of the wheel on every LED.
+    assert_eq!(
+        start,
+        [
+            rgb(255, 0, 0),
+            rgb(63, 192, 0),
+            rgb(0, 126, 129),
+            rgb(66, 0, 189)
+        ]
+    );
+
+    // A quarter of a cycle later, every LED shows the hue of the next
+    // one.
+    let mut later = [BLACK; 4];
+    render(AuraEffect::Rainbow, &params, 1000, &mut later);
+    assert_eq!(later, [start[1], start[2], start[3], start[0]]);
+
+    params.direction = AuraEffectDirection::Reverse;
+    let mut reversed = [BLACK; 4];
+    render(AuraEffect::Rainbow, &params, 0, &mut reversed);
+    assert_eq!(reversed, [start[3], start[2], start[1], start[0]]);
+}
+
+#[test]
+fn leaves_direct_leds_untouched() {
+    let renderer = AuraEffectRenderer::new(AuraEffect::Direct, params(rgb(1, 2, 3)), 2);
+    let mut leds = [rgb(9, 9, 9); 3];
+    renderer.render(500, &mut leds);
+    assert_eq!(leds, [rgb(9, 9, 9); 3]);
+}
+
+fn update(channel: AuraChannel, offset: u8, apply: bool, leds: &[RGB8]) -> RogTerminalMessage {
+    RogTerminalMessage::UpdateLeds {
+        channel,
+        offset,
+        apply,
+        led_data: leds.iter().copied().collect(),
+    }
+}
+
+#[test]
+fn only_shows_applied_frames() {
+    let mut framebuffer = AuraFramebuffer::<2, 4>::new();
+    let red = rgb(255, 0, 0);
+    let blue = rgb(0, 0, 255);
+
+    assert_eq!(
+        framebuffer.apply(&update(AuraChannel::Strip(1), 1, false, &[red, red])),
+        None
+    );
+    assert_eq!(framebuffer.back(1), Some(&[BLACK, red, red, BLACK]));
+    assert_eq!(framebuffer.front(1), Some(&[BLACK; 4]));
+
+    // Colors beyond the end of the channel are discarded.
+    assert_eq!(
+        framebuffer.apply(&update(AuraChannel::Strip(1), 3, true, &[blue, blue])),
+        Some(FrameCommitted { channel: 1 })
+    );
+    assert_eq!(framebuffer.front(1), Some(&[BLACK, red, red, blue]));
+
+    // The back buffer keeps the last frame, so partial updates still
+    // produce whole frames.
+    framebuffer.apply(&update(AuraChannel::Strip(1), 0, true, &[blue]));
+    assert_eq!(framebuffer.front(1), Some(&[blue, red, red, blue]));
+    assert_eq!(framebuffer.front(0), Some(&[BLACK; 4]));
+}
+
+#[test]
+fn reports_each_committed_channel_once() {
+    let mut framebuffer = AuraFramebuffer::<3, 2>::new();
+    let green = rgb(0, 255, 0);
+
+    framebuffer.apply(&update(AuraChannel::Strip(2), 0, true, &[green]));
+    framebuffer.apply(&update(AuraChannel::Strip(0), 0, true, &[green]));
+    framebuffer.apply(&update(AuraChannel::Strip(2), 1, true, &[green]));
+    framebuffer.apply(&update(AuraChannel::Strip(1), 0, false, &[green]));
+
+    assert_eq!(
+        framebuffer.poll_committed(),
+        Some(FrameCommitted { channel: 0 })
+    );
+    assert_eq!(
+        framebuffer.poll_committed(),
+        Some(FrameCommitted { channel: 2 })
+    );
+    assert_eq!(framebuffer.poll_committed(), None);
+
+    framebuffer.apply(&update(AuraChannel::Logo(1), 0, true, &[green]));
+    assert_eq!(
+        framebuffer.poll_committed(),
+        Some(FrameCommitted { channel: 1 })
+    );
+    assert_eq!(framebuffer.poll_committed(), None);
+}
+
+#[test]
+fn ignores_unknown_channels() {
+    let mut framebuffer = AuraFramebuffer::<2, 2>::new();
+    let white = rgb(255, 255, 255);
+
+    assert_eq!(
+        framebuffer.apply(&update(AuraChannel::Strip(2), 0, true, &[white])),
+        None
+    );
+    assert_eq!(framebuffer.update_leds(31, 0, true, &[white]), None);
+    assert_eq!(framebuffer.apply(&RogTerminalMessage::Commit), None);
+    assert_eq!(framebuffer.poll_committed(), None);
+    assert_eq!(framebuffer.front(0), Some(&[BLACK; 2]));
+    assert_eq!(framebuffer.front(1), Some(&[BLACK; 2]));
+    assert_eq!(framebuffer.front(2), None);
+}
+
+/// Records every frame written to it.
+#[derive(Default)]
+struct MockDriver<C> {
+    frames: Vec<Vec<C>>,
+    fail: bool,
+}
+
+impl<C> SmartLedsWrite for MockDriver<C> {
+    type Error = ();
+    type Color = C;
+
+    fn write<T, I>(&mut self, iterator: T) -> Result<(), Self::Error>
+    where
+        T: IntoIterator<Item = I>,
+        I: Into<Self::Color>,
+    {
+        if self.fail {
+            return Err(());
+        }
+
+        self.frames
+            .push(iterator.into_iter().map(Into::into).collect());
+        Ok(())
+    }
+}
+
+fn smart(leds: &[RGB8]) -> Vec<smart_leds_trait::RGB8> {
+    leds.iter().copied().map(to_smart_leds).collect()
+}
+
+#[test]
+fn writes_frames_to_array_drivers() {
+    let drivers: [MockDriver<smart_leds_trait::RGB8>; 3] = Default::default();
+    let mut output = SmartLedsOutput::new(drivers);
+    let mut framebuffer = AuraFramebuffer::<4, 3>::new();
+    let colors = [rgb(1, 2, 3), rgb(4, 5, 6)];
+
+    framebuffer.apply(&update(AuraChannel::Strip(2), 1, true, &colors));
+    framebuffer.apply(&update(AuraChannel::Strip(0), 0, true, &colors));
+    output.write_committed(&mut framebuffer).unwrap();
+
+    let drivers = output.drivers();
+    assert_eq!(drivers[0].frames, [smart(&[colors[0], colors[1], BLACK])]);
+    assert!(drivers[1].frames.is_empty());
+    assert_eq!(drivers[2].frames, [smart(&[BLACK, colors[0], colors[1]])]);
+
+    output.write_framebuffer(&framebuffer, 1).unwrap();
+    assert_eq!(output.drivers()[1].frames, [smart(&[BLACK; 3])]);
+
+    // The framebuffer has a fourth channel, but there is no driver for it.
+    assert_eq!(
+        output.write_framebuffer(&framebuffer, 3),
+        Err(SmartLedsOutputError::UnmappedChannel(3))
+    );
+
+    output.drivers_mut()[1].fail = true;
+    assert_eq!(
+        output.write_framebuffer(&framebuffer, 1),
+        Err(SmartLedsOutputError::Driver(1))
+    );
+}
+
+#[test]
+fn writes_effects_to_tuple_drivers() {
+    let mut output = SmartLedsOutput::new((
+        MockDriver::<smart_leds_trait::RGB8>::default(),
+        RgbwDriver::new(MockDriver::<RGBW<u8>>::default(), WhiteExtraction::Subtract),
+    ));
+    let renderer = AuraEffectRenderer::new(AuraEffect::Static, params(rgb(200, 150, 100)), 2);
+    let mut scratch = [BLACK; 4];
+
+    output.write_effect(&renderer, 0, &mut scratch, 1).unwrap();
+    let (rgb_driver, rgbw_driver) = output.drivers();
+    assert!(rgb_driver.frames.is_empty());
+    let white = RGBW {
+        r: 100,
+        g: 50,
+        b: 0,
+        a: White(100),
+    };
+    assert_eq!(rgbw_driver.driver().frames, [vec![white; 2]]);
+
+    output.write_effect(&renderer, 0, &mut scratch, 0).unwrap();
+    assert_eq!(output.drivers().0.frames, [smart(&[rgb(200, 150, 100); 2])]);
+
+    assert_eq!(
+        output.write_effect(&renderer, 0, &mut scratch, 2),
+        Err(SmartLedsOutputError::UnmappedChannel(2))
+    );
+}
+
+#[test]
+fn builds_gamma_tables() {
+    assert!(GAMMA_LINEAR
+        .iter()
+        .enumerate()
+        .all(|(i, v)| *v as usize == i));
+
+    for table in [&GAMMA_2_2, &GAMMA_2_8] {
+        assert_eq!((table[0], table[255]), (0, 255));
+        assert!(table.windows(2).all(|pair| pair[0] <= pair[1]));
+        // A gamma over 1 darkens the midtones.
+        assert!(table[128] < 128);
+    }
+    assert!(GAMMA_2_8[128] < GAMMA_2_2[128]);
+}
+
+#[test]
+fn leaves_colors_untouched_when_neutral() {
+    let pipeline = ColorPipeline::<2>::new(ChannelColorConfig::new(
+        &GAMMA_LINEAR,
+        WhiteBalance::NEUTRAL,
+    ));
+    assert_eq!(pipeline, ColorPipeline::default());
+
+    for value in 0..=255 {
+        let color = rgb(value, 255 - value, value / 2);
+        assert_eq!(pipeline.process_color(0, color), color);
+    }
+}
+
+const WHITE: RGB8 = RGB8 {
+    r: 255,
+    g: 255,
+    b: 255,
+};
+
+#[test]
+fn dims_frames_over_budget() {
+    // Every white WS2812B LED draws 61 mA.
+    let mut limiter = PowerLimiter::<1>::new(LedPowerModel::WS2812B).with_channel_budget(500);
+    let mut leds = [WHITE; 90];
+
+    let report = limiter.limit(0, &mut leds);
+    assert_eq!(report.requested_ma, 5490);
+    assert!(report.is_limited());
+    assert!(report.drawn_ma <= 500);
+    // The budget is used as much as possible.
+    assert!(report.drawn_ma > 450);
+    assert!(leds.iter().all(|led| *led == leds[0] && led.r < 255));
+    assert_eq!(limiter.drawn_ma(), report.drawn_ma);
+}
+
+#[test]
+fn leaves_frames_under_budget_untouched() {
+    let mut limiter = PowerLimiter::<1>::default().with_channel_budget(1000);
+    let mut leds = [WHITE; 10];
+
+    let report = limiter.limit(0, &mut leds);
+    assert_eq!(report.scale, 255);
+    assert_eq!((report.requested_ma, report.drawn_ma), (610, 610));
+    assert_eq!(leds, [WHITE; 10]);
+}
+
+#[test]
+fn shares_the_total_budget_between_channels() {
+    let mut limiter = PowerLimiter::<2>::default()
+        .with_channel_budget(2000)
+        .with_total_budget(2500);
+    let mut first = [WHITE; 30];
+    let mut second = [WHITE; 30];
+
+    // 1830 mA fits both budgets.
+    assert_eq!(limiter.limit(0, &mut first).scale, 255);
+
+    // The second channel only gets what the first one left.
+    let report = limiter.limit(1, &mut second);
+    assert!(report.is_limited());
+    assert!(report.drawn_ma <= 2500 - 1830);
+    assert!(limiter.drawn_ma() <= 2500);
+
+    // Now the first channel only gets what the second one left, which
+    // is still enough.
+    let report = limiter.limit(0, &mut first);
+    assert_eq!(report.scale, 255);
+    assert!(limiter.drawn_ma() <= 2500);
+
+    // The channel budget applies even with total budget to spare.
+    limiter.reset();
+    let mut leds = [WHITE; 40];
+    let report = limiter.limit(0, &mut leds);
+    assert!(report.is_limited());
+    assert!(report.drawn_ma <= 2000);
+}
+
+#[test]
+fn extracts_white() {
+    let color = rgb(200, 150, 100);
+    assert_eq!(WhiteExtraction::None.extract(color), (color, 0));
+    assert_eq!(
+        WhiteExtraction::Subtract.extract(color),
+        (rgb(100, 50, 0), 100)
+    );
+    assert_eq!(WhiteExtraction::Add.extract(color), (color, 100));
+}
+
+#[test]
+fn encodes_color_orders() {
+    let colors = [rgb(1, 2, 3), rgb(40, 50, 60)];
+    let mut out = [0; 8];
+
+    assert_eq!(ColorOrder::Grb.encode(&colors, &mut out), 6);
+    assert_eq!(out[..6], [2, 1, 3, 50, 40, 60]);
+
+    assert_eq!(ColorOrder::Bgr.encode(&colors, &mut out), 6);
+    assert_eq!(out[..6], [3, 2, 1, 60, 50, 40]);
+
+    assert_eq!(
+        ColorOrder::Rgbw(WhiteExtraction::Subtract).encode(&colors, &mut out),
+        8
+    );
+    assert_eq!(out, [0, 1, 2, 1, 0, 10, 20, 40]);
+
+    assert_eq!(
+        ColorOrder::Grbw(WhiteExtraction::Add).encode(&colors, &mut out),
+        8
+    );
+    assert_eq!(out, [2, 1, 3, 1, 50, 40, 60, 40]);
+
+    // Only whole LEDs are written.
+    assert_eq!(
+        ColorOrder::Grbw(WhiteExtraction::None).encode(&colors, &mut out[..7]),
+        4
+    );
+    assert_eq!(out[..4], [2, 1, 3, 0]);
+}
+
+#[test]
+fn encodes_corrected_colors_in_channel_order() {
+    let pipeline = ColorPipeline::<2>::default()
+        .with_channel(
+            1,
+            ChannelColorConfig::NEUTRAL.with_order(ColorOrder::Grbw(WhiteExtraction::Subtract)),
+        )
+        .with_brightness(127);
+    let colors = [rgb(255, 128, 0), WHITE];
+    let mut out = [0; 8];
+
+    assert_eq!(pipeline.encode(0, &colors, &mut out), 6);
+    assert_eq!(out[..6], [127, 64, 0, 127, 127, 127]);
+
+    assert_eq!(pipeline.encode(1, &colors, &mut out), 8);
+    assert_eq!(out, [64, 127, 0, 0, 0, 0, 0, 127]);
+
+    // Unknown channels are RGB, only scaled by the brightness.
+    assert_eq!(pipeline.encode(2, &colors, &mut out[..5]), 3);
+    assert_eq!(out[..3], [127, 64, 0]);
+}
@@ -0,0 +1,286 @@
+use asus_rog_terminal_usb_device::aura::constants::{
+    AURA_CONFIG_TABLE_MAX_CHANNELS, AURA_MAX_DIRECT_LED_COUNT,
+};
+use asus_rog_terminal_usb_device::aura::{
+    AuraConfigTable, AuraEffect, AuraEffectDirection, AuraEffectParams, AuraInputResponse,
+    AuraOutputRequest, InvalidReportError, RGB8,
+};
+use asus_rog_terminal_usb_device::ROG_AURA_DEFAULT_FIRMWARE_VERSION;
+
+fn rgb(r: u8, g: u8, b: u8) -> RGB8 {
+    RGB8 { r, g, b }
+}
+
+#[test]
+fn round_trips_output_requests() {
+    let requests = [
+        AuraOutputRequest::FirmwareVersion,
+        AuraOutputRequest::ConfigTable,
+        AuraOutputRequest::SetEffect {
+            channel: 3,
+            effect: AuraEffect::ChaseFade,
+            params: AuraEffectParams {
+                color: rgb(1, 2, 3),
+                secondary_color: rgb(4, 5, 6),
+                speed: 0x40,
+                direction: AuraEffectDirection::Reverse,
+                brightness: 0x80,
+                shutdown: true,
+            },
+        },
+        AuraOutputRequest::SetDirectLeds {
+            channel: 0x7f,
+            offset: 0xff,
+            apply: true,
+            led_data: (0..AURA_MAX_DIRECT_LED_COUNT)
+                .map(|i| rgb(i, !i, i * 3))
+                .collect(),
+        },
+        AuraOutputRequest::SetDirectLeds {
+            channel: 1,
+            offset: 0,
+            apply: false,
+            led_data: Default::default(),
+        },
+        AuraOutputRequest::SetMode {
+            channel: 2,
+            effect: AuraEffect::Rainbow,
+            shutdown: false,
+        },
+        AuraOutputRequest::SetEffectColors {
+            led_mask: 0x8001,
+            shutdown: true,
+            colors: core::array::from_fn(|i| rgb(i as u8, 0x10, 0xff - i as u8)),
+        },
+        AuraOutputRequest::Commit,
+    ];
+
+    for request in requests {
+        let report = request.encode();
+        assert_eq!(AuraOutputRequest::decode(&report), Ok(request.clone()));
+        assert_eq!(AuraOutputRequest::decode_slice(&report), Ok(request));
+    }
+}
+
+#[test]
+fn round_trips_input_responses() {
+    let responses = [
+        AuraInputResponse::FirmwareVersion(*ROG_AURA_DEFAULT_FIRMWARE_VERSION),
+        AuraInputResponse::ConfigTable(AuraConfigTable::ROG_TERMINAL),
+        AuraInputResponse::ConfigTable(AuraConfigTable::new().with_channel(12)),
+    ];
+
+    for response in responses {
+        let report = response.encode();
+        assert_eq!(AuraInputResponse::decode(&report), Ok(response.clone()));
+        assert_eq!(AuraInputResponse::decode_slice(&report), Ok(response));
+    }
+}
+
+#[test]
+fn rejects_truncated_reports() {
+    for data in [&[][..], &[0xec]] {
+        assert_eq!(
+            AuraOutputRequest::decode_slice(data),
+            Err(InvalidReportError::Truncated)
+        );
+        assert_eq!(
+            AuraInputResponse::decode_slice(data),
+            Err(InvalidReportError::Truncated)
+        );
+    }
+
+    // A direct LED update for two LEDs, with the last byte missing.
+    let update = [0xec, 0x40, 0x80, 0x00, 0x02, 1, 2, 3, 4, 5, 6];
+    assert_eq!(
+        AuraOutputRequest::decode_slice(&update[..10]),
+        Err(InvalidReportError::Truncated)
+    );
+    assert_eq!(
+        AuraOutputRequest::decode_slice(&update),
+        Ok(AuraOutputRequest::SetDirectLeds {
+            channel: 0,
+            offset: 0,
+            apply: true,
+            led_data: [rgb(1, 2, 3), rgb(4, 5, 6)].into_iter().collect(),
+        })
+    );
+
+    // Other reports are zero-filled.
+    assert_eq!(
+        AuraOutputRequest::decode_slice(&[0xec, 0x3f]),
+        Ok(AuraOutputRequest::Commit)
+    );
+}
+
+#[test]
+fn rejects_out_of_range_led_counts() {
+    let mut report = AuraOutputRequest::SetDirectLeds {
+        channel: 0,
+        offset: 0,
+        apply: true,
+        led_data: Default::default(),
+    }
+    .encode();
+    report[4] = AURA_MAX_DIRECT_LED_COUNT + 1;
+    assert_eq!(
+        AuraOutputRequest::decode(&report),
+        Err(InvalidReportError::LedCountOutOfRange)
+    );
+}
+
+#[test]
+fn checks_the_report_of_decoded_parts() {
+    let effect = AuraOutputRequest::SetEffect {
+        channel: 0,
+        effect: AuraEffect::Static,
+        params: AuraEffectParams::default(),
+    }
+    .encode();
+    assert_eq!(
+        AuraEffectParams::from_report(&effect),
+        Ok(AuraEffectParams::default())
+    );
+
+    let mut report = effect;
+    report[0] = 0x01;
+    assert_eq!(
+        AuraEffectParams::from_report(&report),
+        Err(InvalidReportError::InvalidReportId)
+    );
+    assert_eq!(
+        AuraEffectParams::from_report(&AuraOutputRequest::Commit.encode()),
+        Err(InvalidReportError::InvalidReportType)
+    );
+
+    let mut report = AuraConfigTable::ROG_TERMINAL.to_report();
+    assert_eq!(
+        AuraConfigTable::from_report(&report),
+        Ok(AuraConfigTable::ROG_TERMINAL)
+    );
+    report[0] = 0x01;
+    assert_eq!(
+        AuraConfigTable::from_report(&report),
+        Err(InvalidReportError::InvalidReportId)
+    );
+    let report = AuraInputResponse::FirmwareVersion(*ROG_AURA_DEFAULT_FIRMWARE_VERSION).encode();
+    assert_eq!(
+        AuraConfigTable::from_report(&report),
+        Err(InvalidReportError::InvalidReportType)
+    );
+}
+
+/// Pads a report as sent by OpenRGB, which always writes 65 bytes.
+fn padded(bytes: &[u8]) -> [u8; 65] {
+    let mut report = [0; 65];
+    report[..bytes.len()].copy_from_slice(bytes);
+    report
+}
+
+// Byte sequences written by the Aura USB controllers of OpenRGB.
+const OPENRGB_SET_MODE: &[u8] = &[0xec, 0x35, 0x02, 0x00, 0x00, 0x01];
+const OPENRGB_SET_SHUTDOWN_MODE: &[u8] = &[0xec, 0x35, 0x00, 0x00, 0x01, 0x05];
+const OPENRGB_SET_EFFECT_COLORS: &[u8] = &[
+    0xec, 0x36, 0x00, 0x06, 0x00, 0x00, 0x00, 0x00, 0xff, 0x00, 0x00, 0x00, 0xff, 0x00,
+];
+const OPENRGB_SET_HIGH_EFFECT_COLORS: &[u8] = &[0xec, 0x36, 0x80, 0x00, 0x01];
+const OPENRGB_COMMIT: &[u8] = &[0xec, 0x3f, 0x55];
+
+#[test]
+fn decodes_openrgb_reports() {
+    let cases = [
+        (
+            OPENRGB_SET_MODE,
+            AuraOutputRequest::SetMode {
+                channel: 2,
+                effect: AuraEffect::Static,
+                shutdown: false,
+            },
+        ),
+        (
+            OPENRGB_SET_SHUTDOWN_MODE,
+            AuraOutputRequest::SetMode {
+                channel: 0,
+                effect: AuraEffect::Rainbow,
+                shutdown: true,
+            },
+        ),
+        (
+            OPENRGB_SET_EFFECT_COLORS,
+            AuraOutputRequest::SetEffectColors {
+                // Bytes 2 and 3 hold the mask, big endian.
+                led_mask: 0x0006,
+                shutdown: false,
+                colors: core::array::from_fn(|i| match i {
+                    1 => rgb(0xff, 0, 0),
+                    2 => rgb(0, 0xff, 0),
+                    _ => rgb(0, 0, 0),
+                }),
+            },
+        ),
+        (
+            OPENRGB_SET_HIGH_EFFECT_COLORS,
+            AuraOutputRequest::SetEffectColors {
+                led_mask: 0x8000,
+                shutdown: true,
+                colors: [rgb(0, 0, 0); 16],
+            },
+        ),
+        (OPENRGB_COMMIT, AuraOutputRequest::Commit),
+    ];
+
+    for (bytes, request) in cases {
+        let report = padded(bytes);
+        assert_eq!(AuraOutputRequest::decode(&report), Ok(request.clone()));
+        assert_eq!(AuraOutputRequest::decode_slice(bytes), Ok(request.clone()));
+        assert_eq!(request.encode(), report);
+    }
+}
+
+#[test]
+fn round_trips_the_logo_channel() {
+    let report = AuraConfigTable::ROG_TERMINAL.to_report();
+    // The flag goes along the flags of the fourth channel.
+    assert_eq!(
+        report[10 + 3 * 6 + 5],
+        0x01 | AuraConfigTable::LOGO_CHANNEL_FLAG
+    );
+    assert_eq!(report[10 + 2 * 6 + 5], 0x01);
+
+    for table in [
+        AuraConfigTable::new()
+            .with_channel(30)
+            .with_logo_channel(true),
+        AuraConfigTable::new().with_channel(30),
+        AuraConfigTable::new(),
+    ] {
+        let report = table.to_report();
+        assert_eq!(AuraConfigTable::from_report(&report), Ok(table));
+    }
+
+    // Without channels, there's no entry to carry the flag.
+    assert!(std::panic::catch_unwind(|| AuraConfigTable::new().with_logo_channel(true)).is_err());
+}
+
+#[test]
+fn limits_the_channels_of_config_tables() {
+    let mut table = AuraConfigTable::new();
+    for _ in 0..AURA_CONFIG_TABLE_MAX_CHANNELS {
+        table = table.try_with_channel(10).unwrap();
+    }
+
+    assert_eq!(
+        table.channel_count() as usize,
+        AURA_CONFIG_TABLE_MAX_CHANNELS
+    );
+    assert_eq!(table.try_with_channel(10), None);
+    assert!(std::panic::catch_unwind(|| table.with_channel(10)).is_err());
+
+    let mut report = table.to_report();
+    assert_eq!(AuraConfigTable::from_report(&report), Ok(table));
+    report[6] += 1;
+    assert_eq!(
+        AuraConfigTable::from_report(&report),
+        Err(InvalidReportError::ChannelCountOutOfRange)
+    );
+}
@@ -0,0 +1,958 @@
+use asus_rog_terminal_usb_device::aura::{
+    AuraChannel, AuraConfigTable, AuraEffect, AuraEffectDirection, AuraEffectParams,
+    AuraInputReport, AuraInputResponse, AuraOutputReport, AuraOutputReportType, AuraOutputRequest,
+    InvalidReportError, RGB8,
+};
+use asus_rog_terminal_usb_device::capture::{
+    encode_session_log, import_capture, write_transcript, CaptureFilter,
+};
+use asus_rog_terminal_usb_device::engine::AuraProtocolEngine;
+use asus_rog_terminal_usb_device::events::{AuraEventHandler, AuraHostQuery};
+use asus_rog_terminal_usb_device::host::{
+    AuraClientError, AuraTerminalClient, AuraTransport, LoopbackError, LoopbackTransport,
+};
+use asus_rog_terminal_usb_device::identity::DeviceIdentity;
+use asus_rog_terminal_usb_device::profile::{AuraCommandSet, DeviceProfile};
+use asus_rog_terminal_usb_device::session::{
+    ReplayError, SessionDirection, SessionLogReader, SessionLogWriter, SessionReplayer,
+};
+use asus_rog_terminal_usb_device::stats::AuraError;
+use asus_rog_terminal_usb_device::testing::{SimulatedHost, TestBus, TestHostError};
+use asus_rog_terminal_usb_device::uhid::{UhidDevice, UhidEvent};
+use asus_rog_terminal_usb_device::{
+    rog_terminal_usb_device_builder, AsusRogTerminalHidClass, QueueOverflowPolicy, RawPassthrough,
+    RawReportHandler, RogTerminalMessage, ROG_AURA_DEFAULT_FIRMWARE_VERSION,
+    ROG_AURA_TERMINAL_HID_DESCRIPTOR,
+};
+use std::io::Write;
+
+use usb_device::bus::UsbBusAllocator;
+
+fn rgb(r: u8, g: u8, b: u8) -> RGB8 {
+    RGB8 { r, g, b }
+}
+
+/// Enumerates a ROG Terminal with the default class, once `configure`
+/// has set the class up.
+fn setup<'a, const Q: usize>(
+    alloc: &'a UsbBusAllocator<TestBus>,
+    configure: impl FnOnce(
+        AsusRogTerminalHidClass<'a, TestBus>,
+    ) -> AsusRogTerminalHidClass<'a, TestBus, Q>,
+) -> SimulatedHost<'a, Q> {
+    let class = configure(AsusRogTerminalHidClass::new_with_defaults(alloc));
+    let device = rog_terminal_usb_device_builder(alloc).build();
+    let mut host = SimulatedHost::new(device, class);
+    host.enumerate().unwrap();
+    host
+}
+
+#[test]
+fn enumerates_as_rog_terminal() {
+    let alloc = UsbBusAllocator::new(TestBus::new());
+    let class = AsusRogTerminalHidClass::new_with_defaults(&alloc);
+    let device = rog_terminal_usb_device_builder(&alloc).build();
+    let mut host = SimulatedHost::new(device, class);
+
+    let info = host.enumerate().unwrap();
+    assert_eq!((info.vendor_id, info.product_id), (0x0b05, 0x1889));
+    assert_eq!(info.report_descriptor, ROG_AURA_TERMINAL_HID_DESCRIPTOR);
+}
+
+#[test]
+fn answers_firmware_version_and_config_table() {
+    let alloc = UsbBusAllocator::new(TestBus::new());
+    let table = AuraConfigTable::new().with_channel(30).with_channel(60);
+    let class = AsusRogTerminalHidClass::new(
+        AsusRogTerminalHidClass::build_default_hid_class(&alloc),
+        *ROG_AURA_DEFAULT_FIRMWARE_VERSION,
+        table,
+    );
+    let device = rog_terminal_usb_device_builder(&alloc).build();
+    let mut host = SimulatedHost::new(device, class);
+    host.enumerate().unwrap();
+
+    host.send_output_report(&AuraOutputRequest::FirmwareVersion.encode())
+        .unwrap();
+    let report = host.read_input_report().unwrap().unwrap();
+    assert_eq!(
+        AuraInputResponse::decode(&report),
+        Ok(AuraInputResponse::FirmwareVersion(
+            *ROG_AURA_DEFAULT_FIRMWARE_VERSION
+        ))
+    );
+
+    let mut client = AuraTerminalClient::new(host);
+    let received = client.config_table().unwrap();
+    assert_eq!(received, table);
+    assert_eq!(
+        received
+            .channels()
+            .iter()
+            .map(|c| c.led_count)
+            .collect::<Vec<_>>(),
+        [30, 60]
+    );
+}
+
+#[test]
+fn decodes_direct_leds_and_effects() {
+    let alloc = UsbBusAllocator::new(TestBus::new());
+    let host = setup(&alloc, |class| class);
+
+    let colors: Vec<RGB8> = (0..25).map(|i| rgb(i, 2 * i, 3 * i)).collect();
+    let params = AuraEffectParams {
+        color: rgb(0xff, 0x10, 0x00),
+        speed: 2,
+        direction: AuraEffectDirection::Reverse,
+        ..Default::default()
+    };
+
+    let mut client = AuraTerminalClient::new(host);
+    client.set_direct_leds(2, 5, &colors, true).unwrap();
+    client.set_effect(1, AuraEffect::Breathing, params).unwrap();
+    let mut host = client.into_transport();
+
+    let Some(RogTerminalMessage::UpdateLeds {
+        channel: AuraChannel::Strip(2),
+        offset: 5,
+        apply: false,
+        led_data,
+    }) = host.class_mut().poll_next_message()
+    else {
+        panic!("expected the first chunk of the direct LED update");
+    };
+    assert_eq!(led_data.as_slice(), &colors[..20]);
+
+    let Some(RogTerminalMessage::UpdateLeds {
+        channel: AuraChannel::Strip(2),
+        offset: 25,
+        apply: true,
+        led_data,
+    }) = host.class_mut().poll_next_message()
+    else {
+        panic!("expected the last chunk of the direct LED update");
+    };
+    assert_eq!(led_data.as_slice(), &colors[20..]);
+
+    let Some(RogTerminalMessage::SetEffect {
+        channel: AuraChannel::Strip(1),
+        effect: AuraEffect::Breathing,
+        params: received,
+    }) = host.class_mut().poll_next_message()
+    else {
+        panic!("expected the effect");
+    };
+    assert_eq!(received, params);
+    assert!(host.class_mut().poll_next_message().is_none());
+}
+
+#[test]
+fn stops_led_updates_at_the_last_offset() {
+    let alloc = UsbBusAllocator::new(TestBus::new());
+    let host = setup(&alloc, |class| class);
+
+    let colors: Vec<RGB8> = (0..26).map(|i| rgb(i, i, i)).collect();
+    let mut client = AuraTerminalClient::new(host);
+    assert!(matches!(
+        client.set_direct_leds(0, 231, &colors, true),
+        Err(AuraClientError::OffsetOutOfRange)
+    ));
+    client.set_direct_leds(0, 230, &colors, true).unwrap();
+    let mut host = client.into_transport();
+
+    let offsets: Vec<_> = std::iter::from_fn(|| host.class_mut().poll_next_message())
+        .map(|message| match message {
+            RogTerminalMessage::UpdateLeds { offset, apply, .. } => (offset, apply),
+            other => panic!("unexpected message {other:?}"),
+        })
+        .collect();
+    assert_eq!(offsets, [(230, false), (250, true)]);
+}
+
+#[test]
+fn decodes_openrgb_mode_reports() {
+    let alloc = UsbBusAllocator::new(TestBus::new());
+    let mut host = setup(&alloc, |class| class);
+
+    // As written by OpenRGB: the mode of a channel, the colors of two
+    // LEDs, and the commit.
+    let mut send = |bytes: &[u8]| {
+        let mut report: AuraOutputReport = [0; 65];
+        report[..bytes.len()].copy_from_slice(bytes);
+        host.send_output_report(&report).unwrap();
+    };
+    send(&[0xec, 0x35, 0x01, 0x00, 0x00, 0x02]);
+    send(&[
+        0xec, 0x36, 0x00, 0x03, 0x00, 0xff, 0x00, 0x00, 0x00, 0x00, 0xff,
+    ]);
+    send(&[0xec, 0x3f, 0x55]);
+
+    assert_eq!(
+        host.class_mut().poll_next_message(),
+        Some(RogTerminalMessage::SetMode {
+            channel: AuraChannel::Strip(1),
+            effect: AuraEffect::Breathing,
+            shutdown: false,
+        })
+    );
+
+    let Some(RogTerminalMessage::SetEffectColors {
+        led_mask: 0x0003,
+        shutdown: false,
+        colors,
+    }) = host.class_mut().poll_next_message()
+    else {
+        panic!("expected the effect colors");
+    };
+    assert_eq!(
+        colors[..3],
+        [rgb(0xff, 0, 0), rgb(0, 0, 0xff), rgb(0, 0, 0)]
+    );
+
+    assert_eq!(
+        host.class_mut().poll_next_message(),
+        Some(RogTerminalMessage::Commit)
+    );
+    assert!(host.class_mut().poll_next_message().is_none());
+
+    let stats = host.class().stats();
+    assert_eq!(
+        (
+            stats.set_mode_reports,
+            stats.set_effect_colors_reports,
+            stats.commit_reports
+        ),
+        (1, 1, 1)
+    );
+}
+
+#[test]
+fn addresses_the_logo_channel() {
+    let alloc = UsbBusAllocator::new(TestBus::new());
+    let host = setup(&alloc, |class| class);
+
+    let mut client = AuraTerminalClient::new(host);
+    let logo = client.config_table().unwrap().logo_channel_index().unwrap();
+    assert_eq!(logo, 4);
+
+    client
+        .set_direct_leds(logo, 0, &[rgb(1, 2, 3), rgb(4, 5, 6)], true)
+        .unwrap();
+    client
+        .set_effect(logo, AuraEffect::Static, AuraEffectParams::default())
+        .unwrap();
+    let mut host = client.into_transport();
+
+    let Some(RogTerminalMessage::UpdateLeds {
+        channel: AuraChannel::Logo(4),
+        led_data,
+        ..
+    }) = host.class_mut().poll_next_message()
+    else {
+        panic!("expected a logo LED update");
+    };
+    assert_eq!(led_data.as_slice(), &[rgb(1, 2, 3)]);
+
+    assert!(matches!(
+        host.class_mut().poll_next_message(),
+        Some(RogTerminalMessage::SetEffect {
+            channel: AuraChannel::Logo(4),
+            effect: AuraEffect::Static,
+            ..
+        })
+    ));
+}
+
+#[test]
+fn impersonates_other_profiles() {
+    let profile = DeviceProfile::AURA_ADDRESSABLE_HEADERS;
+    let alloc = UsbBusAllocator::new(TestBus::new());
+    let class = AsusRogTerminalHidClass::from_profile(&alloc, &profile);
+    let device = profile.usb_device_builder(&alloc).build();
+    let mut host = SimulatedHost::new(device, class);
+
+    let info = host.enumerate().unwrap();
+    assert_eq!((info.vendor_id, info.product_id), (0x0b05, 0x18f3));
+
+    let mut client = AuraTerminalClient::new(host);
+    assert_eq!(client.firmware_version().unwrap(), *b"AULA3-AR32-0207");
+    assert_eq!(client.config_table().unwrap(), profile.config_table);
+
+    // The generic controllers don't know about the terminal effects.
+    client
+        .set_effect(0, AuraEffect::Static, AuraEffectParams::default())
+        .unwrap();
+    client.set_direct_leds(0, 0, &[rgb(1, 2, 3)], true).unwrap();
+    let mut host = client.into_transport();
+    assert!(matches!(
+        host.class_mut().poll_next_message(),
+        Some(RogTerminalMessage::UpdateLeds { .. })
+    ));
+    assert!(host.class_mut().poll_next_message().is_none());
+}
+
+#[test]
+fn reports_runtime_identity() {
+    let identity = DeviceIdentity::default()
+        .with_firmware_version_str("CUSTOM-FW-1")
+        .with_serial_from_unique_id(&[0x12, 0x34, 0xab, 0xcd]);
+    let alloc = UsbBusAllocator::new(TestBus::new());
+    let mut class = AsusRogTerminalHidClass::new_with_defaults(&alloc);
+    class.set_identity(&identity);
+    let device = identity
+        .apply(rog_terminal_usb_device_builder(&alloc))
+        .build();
+    let mut host = SimulatedHost::new(device, class);
+    let info = host.enumerate().unwrap();
+
+    let serial_index = info.device_descriptor[16];
+    let descriptor = host
+        .control_in(
+            [0x80, 0x06, serial_index, 0x03, 0x09, 0x04, 0xff, 0x00],
+            0xff,
+        )
+        .unwrap();
+    let serial: String = char::decode_utf16(
+        descriptor[2..]
+            .chunks(2)
+            .map(|c| u16::from_le_bytes([c[0], c[1]])),
+    )
+    .map(Result::unwrap)
+    .collect();
+    assert_eq!(serial, "1234ABCD");
+
+    let mut client = AuraTerminalClient::new(host);
+    assert_eq!(client.firmware_version().unwrap(), *b"CUSTOM-FW-1    ");
+
+    let identity = DeviceIdentity::default().with_serial_number("é-€-😀-1");
+    assert_eq!(identity.serial_number(), Some("?-?-?-1"));
+    let identity = DeviceIdentity::default().with_serial_number(&"ü".repeat(40));
+    assert_eq!(identity.serial_number(), Some("?".repeat(32).as_str()));
+}
+
+#[test]
+fn counts_reports_and_errors() {
+    let alloc = UsbBusAllocator::new(TestBus::new());
+    let mut host = setup(&alloc, |class| class);
+
+    host.send_output_report(&AuraOutputRequest::FirmwareVersion.encode())
+        .unwrap();
+    host.read_input_report().unwrap().unwrap();
+
+    let mut report = AuraOutputRequest::Commit.encode();
+    report[1] = 0x7f;
+    host.send_output_report(&report).unwrap();
+    assert_eq!(
+        host.class().stats().last_error,
+        Some(AuraError::InvalidReport(
+            InvalidReportError::InvalidReportType
+        ))
+    );
+
+    let mut report = AuraOutputRequest::SetDirectLeds {
+        channel: 0,
+        offset: 0,
+        apply: true,
+        led_data: Default::default(),
+    }
+    .encode();
+    report[4] = 30;
+    host.send_output_report(&report).unwrap();
+
+    let stats = *host.class().stats();
+    assert_eq!(stats.firmware_version_requests, 1);
+    assert_eq!(stats.set_direct_leds_reports, 1);
+    assert_eq!(stats.total_reports_received(), 2);
+    assert_eq!(stats.unknown_types, 1);
+    assert_eq!(stats.clamped_led_counts, 1);
+    assert_eq!(stats.last_error, Some(AuraError::LedCountClamped(30)));
+}
+
+fn led_update(offset: u8) -> AuraOutputReport {
+    AuraOutputRequest::SetDirectLeds {
+        channel: 0,
+        offset,
+        apply: true,
+        led_data: [rgb(offset, 0, 0)].into_iter().collect(),
+    }
+    .encode()
+}
+
+fn polled_offset<const Q: usize>(host: &mut SimulatedHost<'_, Q>) -> Option<u8> {
+    match host.class_mut().poll_next_message()? {
+        RogTerminalMessage::UpdateLeds { offset, .. } => Some(offset),
+        message => panic!("unexpected message {message:?}"),
+    }
+}
+
+#[test]
+fn drops_the_oldest_message_on_overflow() {
+    let alloc = UsbBusAllocator::new(TestBus::new());
+    let mut host = setup(&alloc, |class| class.with_queue_capacity::<2>());
+    assert_eq!(
+        host.class().overflow_policy(),
+        QueueOverflowPolicy::DropOldest
+    );
+
+    for offset in 0..3 {
+        host.send_output_report(&led_update(offset)).unwrap();
+    }
+
+    assert_eq!(polled_offset(&mut host), Some(1));
+    assert_eq!(polled_offset(&mut host), Some(2));
+    assert_eq!(polled_offset(&mut host), None);
+    assert_eq!(host.class().stats().dropped_messages, 1);
+}
+
+#[test]
+fn drops_the_newest_message_on_overflow() {
+    let alloc = UsbBusAllocator::new(TestBus::new());
+    let mut host = setup(&alloc, |class| {
+        class
+            .with_queue_capacity::<2>()
+            .with_overflow_policy(QueueOverflowPolicy::DropNewest)
+    });
+
+    for offset in 0..3 {
+        host.send_output_report(&led_update(offset)).unwrap();
+    }
+
+    assert_eq!(polled_offset(&mut host), Some(0));
+    assert_eq!(polled_offset(&mut host), Some(1));
+    assert_eq!(polled_offset(&mut host), None);
+    let stats = host.class().stats();
+    assert_eq!(stats.dropped_messages, 1);
+    assert_eq!(stats.last_error, Some(AuraError::MessageDropped));
+}
+
+#[test]
+fn refuses_reports_while_the_queue_is_full() {
+    let alloc = UsbBusAllocator::new(TestBus::new());
+    let mut host = setup(&alloc, |class| {
+        class
+            .with_queue_capacity::<2>()
+            .with_overflow_policy(QueueOverflowPolicy::Refuse)
+    });
+
+    host.send_output_report(&led_update(0)).unwrap();
+    host.send_output_report(&led_update(1)).unwrap();
+    assert_eq!(
+        host.send_output_report(&led_update(2)),
+        Err(TestHostError::Stalled)
+    );
+
+    let stats = host.class().stats();
+    assert_eq!(stats.refused_reports, 1);
+    assert_eq!(stats.dropped_messages, 0);
+    assert_eq!(stats.last_error, Some(AuraError::ReportRefused));
+
+    assert_eq!(polled_offset(&mut host), Some(0));
+    host.send_output_report(&led_update(3)).unwrap();
+    assert_eq!(polled_offset(&mut host), Some(1));
+    assert_eq!(polled_offset(&mut host), Some(3));
+    assert_eq!(polled_offset(&mut host), None);
+}
+
+#[test]
+fn refuses_loopback_reports_while_the_queue_is_full() {
+    let alloc = UsbBusAllocator::new(TestBus::new());
+    let mut class = AsusRogTerminalHidClass::new_with_defaults(&alloc)
+        .with_queue_capacity::<1>()
+        .with_overflow_policy(QueueOverflowPolicy::Refuse);
+
+    let mut loopback = LoopbackTransport::new(&mut class);
+    loopback.send_output_report(&led_update(0)).unwrap();
+    assert_eq!(
+        loopback.send_output_report(&led_update(1)),
+        Err(LoopbackError::Refused)
+    );
+    assert_eq!(class.stats().refused_reports, 1);
+
+    let Some(RogTerminalMessage::UpdateLeds { offset: 0, .. }) = class.poll_next_message() else {
+        panic!("expected the first update");
+    };
+    assert!(class.poll_next_message().is_none());
+}
+
+#[test]
+fn ignores_reports_to_other_interfaces() {
+    let alloc = UsbBusAllocator::new(TestBus::new());
+    // The interface of another class of a composite device.
+    let other = u8::from(alloc.interface());
+    let mut host = setup(&alloc, |class| {
+        class
+            .with_queue_capacity::<1>()
+            .with_overflow_policy(QueueOverflowPolicy::Refuse)
+    });
+    assert_ne!(host.enumerated().unwrap().hid_interface, other);
+
+    host.send_output_report(&led_update(0)).unwrap();
+    let set_report = [0x21, 0x09, 0xec, 0x02, other, 0, 65, 0];
+    assert_eq!(
+        host.control_out(set_report, &led_update(1)),
+        Err(TestHostError::Stalled)
+    );
+
+    // Nobody took it, but it wasn't refused by the Aura class either.
+    assert_eq!(host.class().stats().refused_reports, 0);
+    assert_eq!(polled_offset(&mut host), Some(0));
+    assert_eq!(polled_offset(&mut host), None);
+}
+
+struct EchoHandler;
+
+impl RawReportHandler for EchoHandler {
+    fn handle_raw_report(&mut self, report: &AuraOutputReport) -> Option<AuraInputReport> {
+        let mut response = [0; 64];
+        response.copy_from_slice(&report[..64]);
+        Some(response)
+    }
+}
+
+#[test]
+fn passes_through_unknown_reports() {
+    let mut handler = EchoHandler;
+    let alloc = UsbBusAllocator::new(TestBus::new());
+    let mut host = setup(&alloc, |class| {
+        class.with_raw_passthrough(RawPassthrough::Unknown)
+    });
+
+    let mut unknown = AuraOutputRequest::Commit.encode();
+    unknown[1] = 0x7f;
+    unknown[2] = 0x42;
+    host.send_output_report(&unknown).unwrap();
+    host.send_output_report(&AuraOutputRequest::Commit.encode())
+        .unwrap();
+
+    assert!(matches!(
+        host.class_mut().poll_next_message(),
+        Some(RogTerminalMessage::Raw(report)) if report == unknown
+    ));
+    assert!(matches!(
+        host.class_mut().poll_next_message(),
+        Some(RogTerminalMessage::Commit)
+    ));
+
+    host.class_mut().set_raw_handler(Some(&mut handler));
+    host.send_output_report(&unknown).unwrap();
+    let response = host.read_input_report().unwrap().unwrap();
+    assert_eq!(response[..], unknown[..64]);
+    assert!(host.class_mut().poll_next_message().is_none());
+}
+
+#[derive(Default)]
+struct RecordingHandler {
+    leds: Vec<(AuraChannel, u8, bool, Vec<RGB8>)>,
+    queries: Vec<AuraHostQuery>,
+    commits: usize,
+}
+
+impl AuraEventHandler for RecordingHandler {
+    fn on_direct_leds(&mut self, channel: AuraChannel, offset: u8, apply: bool, leds: &[RGB8]) {
+        self.leds.push((channel, offset, apply, leds.to_vec()));
+    }
+
+    fn on_commit(&mut self) {
+        self.commits += 1;
+    }
+
+    fn on_host_query(&mut self, query: AuraHostQuery) {
+        self.queries.push(query);
+    }
+}
+
+#[test]
+fn invokes_the_event_handler() {
+    let mut handler = RecordingHandler::default();
+    let alloc = UsbBusAllocator::new(TestBus::new());
+    let host = setup(&alloc, |mut class| {
+        class.set_event_handler(Some(&mut handler));
+        class
+    });
+
+    let mut client = AuraTerminalClient::new(host);
+    client.firmware_version().unwrap();
+    client.set_direct_leds(1, 3, &[rgb(1, 2, 3)], true).unwrap();
+    client.send(&AuraOutputRequest::Commit).unwrap();
+    let mut host = client.into_transport();
+    assert!(host.class_mut().poll_next_message().is_none());
+    drop(host);
+
+    assert_eq!(handler.queries, [AuraHostQuery::FirmwareVersion]);
+    assert_eq!(
+        handler.leds,
+        [(AuraChannel::Strip(1), 3, true, vec![rgb(1, 2, 3)])]
+    );
+    assert_eq!(handler.commits, 1);
+}
+
+#[test]
+fn records_and_replays_sessions() {
+    let mut buf = [0; 1024];
+    let mut clock = 0;
+    let mut recorder = SessionLogWriter::new(&mut buf, || {
+        clock += 1000;
+        clock
+    })
+    .unwrap();
+    let alloc = UsbBusAllocator::new(TestBus::new());
+    let host = setup(&alloc, |mut class| {
+        class.set_recorder(Some(&mut recorder));
+        class
+    });
+
+    let mut client = AuraTerminalClient::new(host);
+    client.firmware_version().unwrap();
+    client.set_direct_leds(0, 0, &[rgb(1, 2, 3)], true).unwrap();
+    client.send(&AuraOutputRequest::Commit).unwrap();
+    drop(client);
+    let log = recorder.as_bytes();
+
+    let records: Vec<_> = SessionLogReader::new(log)
+        .unwrap()
+        .map(|record| record.unwrap())
+        .collect();
+    let directions: Vec<_> = records.iter().map(|record| record.direction).collect();
+    assert_eq!(
+        directions,
+        [
+            SessionDirection::Output,
+            SessionDirection::Input,
+            SessionDirection::Output,
+            SessionDirection::Output,
+        ]
+    );
+    assert_eq!(records[3].timestamp_us, 4000);
+
+    let expected = [
+        RogTerminalMessage::UpdateLeds {
+            channel: AuraChannel::Strip(0),
+            offset: 0,
+            apply: true,
+            led_data: [rgb(1, 2, 3)].into_iter().collect(),
+        },
+        RogTerminalMessage::Commit,
+    ];
+    let replayer = SessionReplayer::new(log).unwrap();
+
+    let mut engine = AuraProtocolEngine::default();
+    let summary = replayer.replay(&mut engine, &expected).unwrap();
+    assert_eq!(
+        (
+            summary.output_reports,
+            summary.input_reports,
+            summary.messages
+        ),
+        (3, 1, 2)
+    );
+
+    let alloc = UsbBusAllocator::new(TestBus::new());
+    let mut host = setup(&alloc, |class| class);
+    replayer.replay(&mut host, &expected).unwrap();
+
+    let mut engine = AuraProtocolEngine::default();
+    engine.set_firmware_version(*b"AULA3-AR32-0207");
+    assert!(matches!(
+        replayer.replay(&mut engine, &expected),
+        Err(ReplayError::ResponseMismatch { record: 1 })
+    ));
+    assert!(matches!(
+        replayer.replay(&mut AuraProtocolEngine::default(), &expected[..1]),
+        Err(ReplayError::UnexpectedMessage { message: 1 })
+    ));
+}
+
+/// Builds a usbmon packet, with the 48 bytes header of
+/// LINKTYPE_USB_LINUX.
+fn usbmon_packet(
+    id: u64,
+    event: u8,
+    xfer_type: u8,
+    endpoint: u8,
+    address: u8,
+    setup: Option<[u8; 8]>,
+    data: &[u8],
+) -> Vec<u8> {
+    let mut packet = vec![0; 48];
+    packet[0..8].copy_from_slice(&id.to_le_bytes());
+    packet[8] = event;
+    packet[9] = xfer_type;
+    packet[10] = endpoint;
+    packet[11] = address;
+    packet[12..14].copy_from_slice(&1u16.to_le_bytes());
+    packet[14] = if setup.is_some() { 0 } else { b'-' };
+    packet[16..24].copy_from_slice(&(id / 10).to_le_bytes());
+    packet[24..28].copy_from_slice(&((id % 10) as u32 * 1000).to_le_bytes());
+    packet[36..40].copy_from_slice(&(data.len() as u32).to_le_bytes());
+    packet[40..48].copy_from_slice(&setup.unwrap_or_default());
+    packet.extend_from_slice(data);
+    packet
+}
+
+fn pcap(packets: &[Vec<u8>]) -> Vec<u8> {
+    let mut capture = vec![0xd4, 0xc3, 0xb2, 0xa1, 2, 0, 4, 0];
+    capture.extend_from_slice(&[0; 8]);
+    capture.extend_from_slice(&0xffffu32.to_le_bytes());
+    capture.extend_from_slice(&189u32.to_le_bytes());
+    for packet in packets {
+        capture.extend_from_slice(&[0; 8]);
+        capture.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+        capture.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+        capture.extend_from_slice(packet);
+    }
+    capture
+}
+
+fn pcapng(packets: &[Vec<u8>]) -> Vec<u8> {
+    fn block(capture: &mut Vec<u8>, block_type: u32, body: &[u8]) {
+        let padding = (4 - body.len() % 4) % 4;
+        let len = (12 + body.len() + padding) as u32;
+        capture.extend_from_slice(&block_type.to_le_bytes());
+        capture.extend_from_slice(&len.to_le_bytes());
+        capture.extend_from_slice(body);
+        capture.extend_from_slice(&vec![0; padding]);
+        capture.extend_from_slice(&len.to_le_bytes());
+    }
+
+    let mut capture = Vec::new();
+    let mut header = 0x1a2b3c4du32.to_le_bytes().to_vec();
+    header.extend_from_slice(&[1, 0, 0, 0, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]);
+    block(&mut capture, 0x0a0d0d0a, &header);
+    block(&mut capture, 1, &[189, 0, 0, 0, 0, 0, 0, 0]);
+    for packet in packets {
+        let mut body = vec![0; 12];
+        body.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+        body.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+        body.extend_from_slice(packet);
+        block(&mut capture, 6, &body);
+    }
+    capture
+}
+
+#[test]
+fn imports_usbmon_captures() {
+    let mut descriptor = vec![18, 1, 0, 2, 0, 0, 0, 64];
+    descriptor.extend_from_slice(&0x0b05u16.to_le_bytes());
+    descriptor.extend_from_slice(&0x1889u16.to_le_bytes());
+    descriptor.extend_from_slice(&[0, 1, 1, 2, 0, 1]);
+
+    let request = AuraOutputRequest::FirmwareVersion.encode();
+    let response = AuraInputResponse::FirmwareVersion(*ROG_AURA_DEFAULT_FIRMWARE_VERSION).encode();
+    let get_descriptor = [0x80, 0x06, 0x00, 0x01, 0x00, 0x00, 18, 0];
+    let set_report = [0x21, 0x09, 0xec, 0x02, 0x02, 0x00, 65, 0];
+    let packets = [
+        usbmon_packet(10, b'S', 2, 0x80, 5, Some(get_descriptor), &[]),
+        usbmon_packet(10, b'C', 2, 0x80, 5, None, &descriptor),
+        usbmon_packet(21, b'S', 2, 0x00, 5, Some(set_report), &request),
+        usbmon_packet(21, b'C', 2, 0x00, 5, None, &[]),
+        usbmon_packet(23, b'C', 1, 0x84, 5, None, &response),
+        // The same report, to another device.
+        usbmon_packet(30, b'S', 2, 0x00, 6, Some(set_report), &request),
+        usbmon_packet(30, b'C', 2, 0x00, 6, None, &[]),
+    ];
+
+    for capture in [pcap(&packets), pcapng(&packets)] {
+        let records = import_capture(&capture, &CaptureFilter::ROG_TERMINAL).unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].output_report(), Some(request));
+        assert_eq!(records[1].input_report(), Some(response));
+        assert_eq!(records[1].timestamp_us, 2000);
+
+        let mut transcript = String::new();
+        write_transcript(&records, &mut transcript).unwrap();
+        assert_eq!(
+            transcript.lines().next(),
+            Some("     0.000000 host -> device FirmwareVersion")
+        );
+
+        let log = encode_session_log(&records);
+        SessionReplayer::new(&log)
+            .unwrap()
+            .replay(&mut AuraProtocolEngine::default(), &[])
+            .unwrap();
+    }
+
+    let filter = CaptureFilter::ROG_TERMINAL.with_device(1, 6);
+    let records = import_capture(&pcap(&packets[5..]), &filter).unwrap();
+    assert_eq!(records.len(), 1);
+}
+
+#[test]
+fn flags_truncated_input_reports() {
+    let response = AuraInputResponse::FirmwareVersion(*ROG_AURA_DEFAULT_FIRMWARE_VERSION).encode();
+    let mut long = response.to_vec();
+    long.push(0xaa);
+    let packets = [usbmon_packet(10, b'C', 1, 0x84, 5, None, &long)];
+
+    let filter = CaptureFilter::ROG_TERMINAL.with_device(1, 5);
+    let records = import_capture(&pcap(&packets), &filter).unwrap();
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].input_report(), Some(response));
+    assert_eq!(records[0].truncated_bytes(), 1);
+
+    let mut transcript = String::new();
+    write_transcript(&records, &mut transcript).unwrap();
+    assert!(transcript.ends_with(" (truncated from 65 bytes)\n"));
+
+    // Session logs only keep the report.
+    let log = encode_session_log(&records);
+    let replayed = SessionLogReader::new(&log)
+        .unwrap()
+        .next()
+        .unwrap()
+        .unwrap();
+    assert_eq!(replayed.input_report(), Some(response));
+    assert_eq!(replayed.truncated_bytes(), 0);
+}
+
+#[test]
+fn talks_uhid() {
+    const EVENT_SIZE: usize = 4380;
+
+    // A regular file stands in for /dev/uhid: the device writes its
+    // events at the end, and reads the ones appended to it.
+    let path = std::env::temp_dir().join(format!("aura-uhid-{}", std::process::id()));
+    std::fs::write(&path, []).unwrap();
+    let append = |event: &[u8]| {
+        std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(event)
+            .unwrap();
+    };
+    let set_report = |id: u32, report: &AuraOutputReport| {
+        let mut event = vec![0; EVENT_SIZE];
+        event[..4].copy_from_slice(&13u32.to_ne_bytes());
+        event[4..8].copy_from_slice(&id.to_ne_bytes());
+        event[4 + 5] = 1;
+        event[4 + 6..4 + 8].copy_from_slice(&65u16.to_ne_bytes());
+        event[4 + 8..4 + 8 + 65].copy_from_slice(report);
+        event
+    };
+
+    let profile = DeviceProfile::ROG_TERMINAL;
+    let engine = AuraProtocolEngine::from_profile(&profile)
+        .with_queue_capacity::<1>()
+        .with_overflow_policy(QueueOverflowPolicy::Refuse);
+    let mut device = UhidDevice::create_at(&path, &profile, engine).unwrap();
+
+    let mut output = vec![0; EVENT_SIZE];
+    output[..4].copy_from_slice(&6u32.to_ne_bytes());
+    output[4..4 + 65].copy_from_slice(&AuraOutputRequest::FirmwareVersion.encode());
+    output[4 + 4096..4 + 4098].copy_from_slice(&65u16.to_ne_bytes());
+    output[4 + 4098] = 1;
+    append(&output);
+    assert_eq!(device.process_event().unwrap(), UhidEvent::Report);
+
+    // The queue only fits one message, so the second report is refused.
+    let commit = AuraOutputRequest::Commit.encode();
+    append(&set_report(1, &commit));
+    assert_eq!(device.process_event().unwrap(), UhidEvent::Report);
+    append(&set_report(2, &commit));
+    assert_eq!(device.process_event().unwrap(), UhidEvent::Report);
+    assert_eq!(device.engine().stats().refused_reports, 1);
+    assert_eq!(
+        device.engine_mut().poll_next_message(),
+        Some(RogTerminalMessage::Commit)
+    );
+
+    drop(device);
+    let events = std::fs::read(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let event = |index: usize| &events[index * EVENT_SIZE..(index + 1) * EVENT_SIZE];
+
+    let create = event(0);
+    assert_eq!(create[..4], 11u32.to_ne_bytes());
+    assert!(create[4..].starts_with(b"ROG AURA TERMINAL\0"));
+    assert_eq!(create[264..268], 0x0b05u32.to_ne_bytes());
+    assert_eq!(create[268..272], 0x1889u32.to_ne_bytes());
+    assert_eq!(
+        create[280..280 + ROG_AURA_TERMINAL_HID_DESCRIPTOR.len()],
+        ROG_AURA_TERMINAL_HID_DESCRIPTOR
+    );
+
+    let input = event(2);
+    assert_eq!(input[..4], 12u32.to_ne_bytes());
+    assert_eq!(input[4..6], 64u16.to_ne_bytes());
+    assert_eq!(
+        input[6..6 + 64],
+        AuraInputResponse::FirmwareVersion(*ROG_AURA_DEFAULT_FIRMWARE_VERSION).encode()
+    );
+
+    // SET_REPORT_REPLY: the request ID, then the error.
+    let accepted = event(4);
+    assert_eq!(accepted[..4], 14u32.to_ne_bytes());
+    assert_eq!(accepted[4..8], 1u32.to_ne_bytes());
+    assert_eq!(accepted[8..10], 0u16.to_ne_bytes());
+
+    let refused = event(6);
+    assert_eq!(refused[..4], 14u32.to_ne_bytes());
+    assert_eq!(refused[4..8], 2u32.to_ne_bytes());
+    assert_eq!(refused[8..10], 5u16.to_ne_bytes());
+
+    let destroy = event(7);
+    assert_eq!(destroy[..4], 1u32.to_ne_bytes());
+    assert_eq!(events.len(), 8 * EVENT_SIZE);
+}
+
+#[test]
+fn runs_the_engine_without_usb() {
+    let mut engine = AuraProtocolEngine::from_profile(&DeviceProfile::ROG_TERMINAL);
+
+    // Trimmed reports, as a bridge that drops the trailing zeros would
+    // deliver them.
+    engine.handle_output_slice(&[0xec, 0x82]);
+    engine.handle_output_slice(&[0xec, 0x40, 0x82, 0x02, 0x01, 0x10, 0x20, 0x30]);
+    assert!(engine.pop_response().is_some_and(|response| {
+        AuraInputResponse::decode(&response)
+            == Ok(AuraInputResponse::FirmwareVersion(
+                *ROG_AURA_DEFAULT_FIRMWARE_VERSION,
+            ))
+    }));
+    assert!(engine.pop_response().is_none());
+
+    assert_eq!(
+        engine.poll_next_message(),
+        Some(RogTerminalMessage::UpdateLeds {
+            channel: AuraChannel::Strip(2),
+            offset: 2,
+            apply: true,
+            led_data: [rgb(0x10, 0x20, 0x30)].into_iter().collect(),
+        })
+    );
+    assert_eq!(engine.stats().total_reports_received(), 2);
+
+    let mut engine = AuraProtocolEngine::from_profile(&DeviceProfile::ROG_TERMINAL)
+        .with_queue_capacity::<1>()
+        .with_overflow_policy(QueueOverflowPolicy::Refuse);
+    assert!(engine.admit_report());
+    engine.handle_output_slice(&AuraOutputRequest::Commit.encode());
+    assert!(!engine.admit_report());
+    assert_eq!(engine.stats().refused_reports, 1);
+    assert_eq!(engine.poll_next_message(), Some(RogTerminalMessage::Commit));
+    assert!(engine.admit_report());
+}
+
+#[test]
+fn drops_unsupported_commands_before_decoding() {
+    let mut engine = AuraProtocolEngine::default()
+        .with_supported_commands(AuraCommandSet::ALL.without(AuraOutputReportType::SetDirectLeds));
+
+    // An LED count over the limit would be clamped if it was decoded.
+    let mut report = led_update(0);
+    report[4] = 0xff;
+    engine.handle_output_report(&report);
+
+    let stats = engine.stats();
+    assert_eq!(stats.unsupported_commands, 1);
+    assert_eq!(stats.clamped_led_counts, 0);
+    assert_eq!(
+        stats.last_error,
+        Some(AuraError::UnsupportedCommand(
+            AuraOutputReportType::SetDirectLeds as u8
+        ))
+    );
+    assert!(engine.poll_next_message().is_none());
+}